@@ -0,0 +1,211 @@
+//! Async access to a `Launchpad`, enabled by the `async` feature.
+//!
+//! Pad events arrive as a `futures_core::Stream`, and lights are set through
+//! a writer task, so nothing here blocks the async runtime.
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{Launchpad, LaunchpadColor, LaunchpadState};
+use crate::button::Button;
+use crate::color::{ColorProfile, LightMode};
+use crate::error::LaunchpadError;
+use crate::event::*;
+
+/// Pad events from a `Launchpad`, as a `Stream`.
+///
+/// The stream ends once the launchpad is dropped, and dropping the stream
+/// unsubscribes it.
+pub struct EventStream {
+    receiver: mpsc::UnboundedReceiver<TimedEvent>,
+    _subscription: Subscription
+}
+
+impl EventStream {
+    /// Subscribes a new stream to `event`.
+    pub fn new(event: &Arc<Mutex<LaunchpadEvent>>) -> EventStream {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let subscription = event.lock().unwrap().subscribe_handler(Box::new(StreamHandler { sender }));
+
+        EventStream { receiver, _subscription: subscription }
+    }
+
+    /// Waits for the next event, for callers not using `StreamExt`.
+    pub async fn next(&mut self) -> Option<TimedEvent> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = TimedEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<TimedEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+struct StreamHandler {
+    sender: mpsc::UnboundedSender<TimedEvent>
+}
+
+impl LaunchpadEventHandler for StreamHandler {
+    fn notify(&self, args: &LaunchpadEventArgs) {
+        self.notify_at(args, EventTime::now());
+    }
+
+    fn notify_at(&self, args: &LaunchpadEventArgs, time: EventTime) {
+        // the stream may have been dropped, which is not an error here.
+        let _ = self.sender.send(TimedEvent { args: args.clone(), time });
+    }
+}
+
+type Command = Box<dyn FnOnce(&mut dyn Launchpad) + Send>;
+
+/// A `Launchpad` driven from async code.
+///
+/// The device is moved onto a blocking writer task, which runs the async
+/// versions of the `LaunchpadOutput` methods one at a time. The task stops,
+/// dropping the device, once this handle is dropped. Must be created inside
+/// a tokio runtime.
+pub struct AsyncLaunchpad {
+    commands: mpsc::UnboundedSender<Command>,
+    event: Arc<Mutex<LaunchpadEvent>>,
+    grid_size: (usize, usize)
+}
+
+impl AsyncLaunchpad {
+    pub fn new(mut launchpad: Box<dyn Launchpad>) -> AsyncLaunchpad {
+        let event = launchpad.get_event().clone();
+        let grid_size = launchpad.grid_size();
+
+        let (commands, mut receiver) = mpsc::unbounded_channel::<Command>();
+        tokio::task::spawn_blocking(move || {
+            while let Some(command) = receiver.blocking_recv() {
+                command(&mut *launchpad);
+            }
+        });
+
+        AsyncLaunchpad { commands, event, grid_size }
+    }
+
+    /// Pad events from now on.
+    pub fn events(&self) -> EventStream {
+        EventStream::new(&self.event)
+    }
+
+    pub fn grid_size(&self) -> (usize, usize) {
+        self.grid_size
+    }
+
+    /// Runs `command` on the writer task and waits for its result.
+    async fn run<T, F>(&self, command: F) -> Result<T, LaunchpadError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Launchpad) -> Result<T, LaunchpadError> + Send + 'static
+    {
+        let stopped = || LaunchpadError::SendFailed("writer task has stopped".to_string());
+
+        let (reply, result) = oneshot::channel();
+        let command: Command = Box::new(move |launchpad| {
+            let _ = reply.send(command(launchpad));
+        });
+        self.commands.send(command).map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+
+    pub async fn set_lights(&self, lights: Vec<(usize, usize, LaunchpadColor)>) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_lights(&lights)).await
+    }
+
+    pub async fn set_lights_with_mode(&self, lights: Vec<(usize, usize, LaunchpadColor, LightMode)>) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_lights_with_mode(&lights)).await
+    }
+
+    pub async fn set_color_profile(&self, profile: Option<ColorProfile>) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| {
+            launchpad.set_color_profile(profile);
+            Ok(())
+        }).await
+    }
+
+    pub async fn set_brightness(&self, level: u8) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_brightness(level)).await
+    }
+
+    /// Asks the device for its LED brightness. Must not be waited on from an
+    /// event handler, for the same reason as `LaunchpadOutput::brightness`.
+    pub async fn brightness(&self) -> Result<u8, LaunchpadError> {
+        self.run(|launchpad| launchpad.brightness()).await
+    }
+
+    pub async fn sleep(&self) -> Result<(), LaunchpadError> {
+        self.run(|launchpad| launchpad.sleep()).await
+    }
+
+    pub async fn wake(&self) -> Result<(), LaunchpadError> {
+        self.run(|launchpad| launchpad.wake()).await
+    }
+
+    /// Asks the device whether its LEDs are asleep. Must not be waited on
+    /// from an event handler, for the same reason as `LaunchpadOutput::brightness`.
+    pub async fn is_sleeping(&self) -> Result<bool, LaunchpadError> {
+        self.run(|launchpad| launchpad.is_sleeping()).await
+    }
+
+    pub async fn set_all_lights(&self, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_all_lights(color)).await
+    }
+
+    pub async fn set_light(&self, x: usize, y: usize, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_light(x, y, color)).await
+    }
+
+    pub async fn set_button_light(&self, button: Button, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_button_light(button, color)).await
+    }
+
+    pub async fn set_state(&self, lights: LaunchpadState) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_state(lights)).await
+    }
+
+    pub async fn clear_grid(&self) -> Result<(), LaunchpadError> {
+        self.run(|launchpad| launchpad.clear_grid()).await
+    }
+
+    pub async fn set_box(&self, x: usize, y: usize, width: usize, height: usize, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        self.run(move |launchpad| launchpad.set_box(x, y, width, height, color)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockLaunchpad;
+
+    #[test]
+    fn sets_lights_and_streams_events() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        runtime.block_on(async {
+            let mock = MockLaunchpad::new();
+            let launchpad = AsyncLaunchpad::new(Box::new(mock.clone()));
+            let mut events = launchpad.events();
+
+            launchpad.set_light(1, 2, LaunchpadColor::RED).await.unwrap();
+            assert_eq!(mock.get_light(1, 2).red, 127);
+            assert!(launchpad.set_light(9, 9, LaunchpadColor::RED).await.is_err());
+
+            mock.press(4, 4);
+            assert_eq!(events.next().await.map(|event| event.args), Some(LaunchpadEventArgs::Pressed {
+                x: 4, y: 4, button: Button::Grid { x: 4, y: 3 }, velocity: 127
+            }));
+
+            drop(events);
+            assert_eq!(mock.get_event().lock().unwrap().subscriber_count(), 0);
+        });
+    }
+}
@@ -0,0 +1,153 @@
+/// A pad or control button, by what it is rather than where it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    /// One of the 8x8 pads, counted from the top left.
+    Grid { x: usize, y: usize },
+    Up,
+    Down,
+    Left,
+    Right,
+    Session,
+    Note,
+    Custom,
+    CaptureMidi,
+    /// The User 1 button of the MK2 and S.
+    User1,
+    /// The User 2 button of the MK2 and S.
+    User2,
+    /// The Mixer button of the MK2 and S.
+    Mixer,
+    /// A scene launch button on the right of the grid, counted from the top.
+    Scene(usize),
+    Logo,
+    /// A button this library has no name for, at its grid position.
+    Other { x: usize, y: usize }
+}
+
+/// Where a model puts its buttons in the grid of lights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonLayout {
+    /// A 9x9 grid with the control buttons along the top, the logo in the top
+    /// right corner and the scene buttons down the right, as on the Launchpad X
+    /// and Mini MK3.
+    Standard,
+    /// The 9x9 grid of the Launchpad MK2 and S, which have User 1, User 2 and
+    /// Mixer where the X has Note, Custom and Capture MIDI, and no logo.
+    Classic,
+    /// The 10x10 ring of buttons of the Launchpad Pro MK3.
+    Pro
+}
+
+impl ButtonLayout {
+    const STANDARD_TOP_ROW: [Button; 8] = [
+        Button::Up, Button::Down, Button::Left, Button::Right,
+        Button::Session, Button::Note, Button::Custom, Button::CaptureMidi
+    ];
+
+    const CLASSIC_TOP_ROW: [Button; 8] = [
+        Button::Up, Button::Down, Button::Left, Button::Right,
+        Button::Session, Button::User1, Button::User2, Button::Mixer
+    ];
+
+    // the Pro MK3 has the arrows split between the top row and the left column.
+    const PRO_BUTTONS: [(usize, usize, Button); 8] = [
+        (0, 1, Button::Up),
+        (0, 2, Button::Down),
+        (1, 0, Button::Left),
+        (2, 0, Button::Right),
+        (3, 0, Button::Session),
+        (4, 0, Button::Note),
+        (6, 0, Button::Custom),
+        (9, 0, Button::Logo)
+    ];
+
+    /// The button at `(x, y)` in the grid of lights.
+    pub fn button_at(self, x: usize, y: usize) -> Button {
+        match self {
+            ButtonLayout::Standard => match (x, y) {
+                (0..=7, 0) => ButtonLayout::STANDARD_TOP_ROW[x],
+                (8, 0) => Button::Logo,
+                (8, 1..=8) => Button::Scene(y - 1),
+                (0..=7, 1..=8) => Button::Grid { x, y: y - 1 },
+                _ => Button::Other { x, y }
+            },
+            ButtonLayout::Classic => match (x, y) {
+                (0..=7, 0) => ButtonLayout::CLASSIC_TOP_ROW[x],
+                (8, 1..=8) => Button::Scene(y - 1),
+                (0..=7, 1..=8) => Button::Grid { x, y: y - 1 },
+                _ => Button::Other { x, y }
+            },
+            ButtonLayout::Pro => match (x, y) {
+                (9, 1..=8) => Button::Scene(y - 1),
+                (1..=8, 1..=8) => Button::Grid { x: x - 1, y: y - 1 },
+                _ => ButtonLayout::PRO_BUTTONS.iter()
+                    .find(|(bx, by, _)| (*bx, *by) == (x, y))
+                    .map_or(Button::Other { x, y }, |(_, _, button)| *button)
+            }
+        }
+    }
+
+    /// The position of `button` in the grid of lights, if this layout has it.
+    pub fn position(self, button: Button) -> Option<(usize, usize)> {
+        match (self, button) {
+            (_, Button::Other { x, y }) => Some((x, y)),
+            (_, Button::Grid { x, y }) if x >= 8 || y >= 8 => None,
+            (_, Button::Scene(row)) if row >= 8 => None,
+
+            (ButtonLayout::Standard | ButtonLayout::Classic, Button::Grid { x, y }) => Some((x, y + 1)),
+            (ButtonLayout::Standard | ButtonLayout::Classic, Button::Scene(row)) => Some((8, row + 1)),
+            (ButtonLayout::Standard, Button::Logo) => Some((8, 0)),
+            (ButtonLayout::Standard, button) => ButtonLayout::STANDARD_TOP_ROW.iter()
+                .position(|b| *b == button)
+                .map(|x| (x, 0)),
+            (ButtonLayout::Classic, button) => ButtonLayout::CLASSIC_TOP_ROW.iter()
+                .position(|b| *b == button)
+                .map(|x| (x, 0)),
+
+            (ButtonLayout::Pro, Button::Grid { x, y }) => Some((x + 1, y + 1)),
+            (ButtonLayout::Pro, Button::Scene(row)) => Some((9, row + 1)),
+            (ButtonLayout::Pro, button) => ButtonLayout::PRO_BUTTONS.iter()
+                .find(|(_, _, b)| *b == button)
+                .map(|(x, y, _)| (*x, *y))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_standard_buttons() {
+        assert_eq!(ButtonLayout::Standard.button_at(0, 0), Button::Up);
+        assert_eq!(ButtonLayout::Standard.button_at(7, 0), Button::CaptureMidi);
+        assert_eq!(ButtonLayout::Standard.button_at(8, 0), Button::Logo);
+        assert_eq!(ButtonLayout::Standard.button_at(8, 1), Button::Scene(0));
+        assert_eq!(ButtonLayout::Standard.button_at(3, 8), Button::Grid { x: 3, y: 7 });
+        assert_eq!(ButtonLayout::Standard.button_at(9, 0), Button::Other { x: 9, y: 0 });
+
+        assert_eq!(ButtonLayout::Classic.button_at(5, 0), Button::User1);
+        assert_eq!(ButtonLayout::Classic.button_at(7, 0), Button::Mixer);
+        assert_eq!(ButtonLayout::Classic.button_at(8, 0), Button::Other { x: 8, y: 0 });
+
+        assert_eq!(ButtonLayout::Pro.button_at(1, 1), Button::Grid { x: 0, y: 0 });
+        assert_eq!(ButtonLayout::Pro.button_at(0, 2), Button::Down);
+        assert_eq!(ButtonLayout::Pro.button_at(0, 5), Button::Other { x: 0, y: 5 });
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        for layout in [ButtonLayout::Standard, ButtonLayout::Classic, ButtonLayout::Pro] {
+            for y in 0..10 {
+                for x in 0..10 {
+                    assert_eq!(layout.position(layout.button_at(x, y)), Some((x, y)));
+                }
+            }
+        }
+
+        assert_eq!(ButtonLayout::Standard.position(Button::Grid { x: 8, y: 0 }), None);
+        assert_eq!(ButtonLayout::Pro.position(Button::CaptureMidi), None);
+        assert_eq!(ButtonLayout::Classic.position(Button::Note), None);
+        assert_eq!(ButtonLayout::Standard.position(Button::Mixer), None);
+    }
+}
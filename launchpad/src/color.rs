@@ -1,4 +1,8 @@
-#[derive(Copy, Clone)]
+use crate::error::LaunchpadError;
+use crate::palette;
+
+/// An RGB color with 7 bit channels, from 0 to 127, as the devices take them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
 pub struct LaunchpadColor {
     pub red:   u8,
     pub green: u8,
@@ -10,4 +14,378 @@ impl LaunchpadColor {
     pub const RED: LaunchpadColor = LaunchpadColor   { red: 127, green: 0,   blue: 0   };
     pub const GREEN: LaunchpadColor = LaunchpadColor { red: 0,   green: 127, blue: 0   };
     pub const BLUE: LaunchpadColor = LaunchpadColor  { red: 0,   green: 0,   blue: 127 };
-}
\ No newline at end of file
+
+    /// A color from 8 bit channels, from 0 to 255, as used on the web.
+    pub const fn from_rgb8(red: u8, green: u8, blue: u8) -> LaunchpadColor {
+        LaunchpadColor { red: red >> 1, green: green >> 1, blue: blue >> 1 }
+    }
+
+    /// Parses an 8 bit `#rrggbb` or `#rgb` color, with or without the `#`.
+    pub fn from_hex(hex: &str) -> Option<LaunchpadColor> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return None;
+        }
+        let value = u32::from_str_radix(digits, 16).ok()?;
+
+        match digits.len() {
+            6 => Some(LaunchpadColor::from_u32(value)),
+            3 => {
+                let channel = |digit: u32| (digit & 0xF) as u8 * 0x11;
+                Some(LaunchpadColor::from_rgb8(channel(value >> 8), channel(value >> 4), channel(value)))
+            },
+            _ => None
+        }
+    }
+
+    /// A color from its hue in degrees, and its saturation and value from 0 to 1.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> LaunchpadColor {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let saturation = saturation.clamp(0.0, 1.0);
+        let value = value.clamp(0.0, 1.0);
+
+        let chroma = value * saturation;
+        let second = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (red, green, blue) = match hue as u32 {
+            0 => (chroma, second, 0.0),
+            1 => (second, chroma, 0.0),
+            2 => (0.0, chroma, second),
+            3 => (0.0, second, chroma),
+            4 => (second, 0.0, chroma),
+            _ => (chroma, 0.0, second)
+        };
+
+        let channel = |c: f32| ((c + value - chroma) * 127.0).round() as u8;
+        LaunchpadColor { red: channel(red), green: channel(green), blue: channel(blue) }
+    }
+
+    /// Looks up a CSS color name, such as `"teal"` or `"DeepSkyBlue"`.
+    pub fn from_name(name: &str) -> Option<LaunchpadColor> {
+        let name = name.to_ascii_lowercase();
+        NAMED_COLORS.binary_search_by(|(named, _)| named.cmp(&name.as_str()))
+            .ok()
+            .map(|index| LaunchpadColor::from_u32(NAMED_COLORS[index].1))
+    }
+
+    pub(crate) const fn from_u32(hex: u32) -> LaunchpadColor {
+        LaunchpadColor::from_rgb8((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+    }
+
+    /// The color `t` of the way from this color to `other`, with `t` from 0 to 1.
+    pub fn lerp(&self, other: LaunchpadColor, t: f32) -> LaunchpadColor {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |from: u8, to: u8| (f32::from(from) + (f32::from(to) - f32::from(from)) * t).round() as u8;
+
+        LaunchpadColor {
+            red: channel(self.red, other.red),
+            green: channel(self.green, other.green),
+            blue: channel(self.blue, other.blue)
+        }
+    }
+
+    /// This color at `brightness`, where 1 leaves it as it is. Channels are
+    /// capped at 127.
+    pub fn scale(&self, brightness: f32) -> LaunchpadColor {
+        let brightness = brightness.max(0.0);
+        let channel = |c: u8| (f32::from(c) * brightness).round().min(127.0) as u8;
+
+        LaunchpadColor { red: channel(self.red), green: channel(self.green), blue: channel(self.blue) }
+    }
+
+    /// The color at `index` in the Launchpad X palette, if there is one.
+    pub fn from_palette(index: u8) -> Option<LaunchpadColor> {
+        palette::PALETTE.get(usize::from(index)).copied()
+    }
+
+    /// The index of the nearest color in the Launchpad X palette, compared
+    /// by how alike they look rather than by their RGB values.
+    pub fn to_palette(&self) -> u8 {
+        palette::nearest(*self)
+    }
+}
+
+/// Corrects colors for how a device's LEDs show them, so that gradients and
+/// images look right on the hardware.
+///
+/// Each channel is scaled to 0-1, raised to its `gamma`, then multiplied by
+/// its `white_balance`. LEDs are much brighter at low levels than their value
+/// suggests, so a gamma above 1 dims the middle of the range; a white balance
+/// below 1 tones down a channel that overpowers the others in white.
+///
+/// A gamma that is not finite and positive is treated as 1. White balance is
+/// clamped to 0-1, and treated as 1 if it is not finite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorProfile {
+    /// Red, green and blue gamma curves.
+    pub gamma: [f32; 3],
+    /// Red, green and blue scale factors, from 0 to 1.
+    pub white_balance: [f32; 3]
+}
+
+impl ColorProfile {
+    /// A profile with the same gamma for every channel and no white balance.
+    pub fn with_gamma(gamma: f32) -> ColorProfile {
+        ColorProfile { gamma: [gamma; 3], white_balance: [1.0; 3] }
+    }
+
+    /// The color to send for `color` to be shown correctly.
+    pub fn apply(&self, color: LaunchpadColor) -> LaunchpadColor {
+        let channel = |value: u8, index: usize| {
+            // a gamma of 0 or below would turn black into full brightness.
+            let gamma = match self.gamma[index] {
+                gamma if gamma.is_finite() && gamma > 0.0 => gamma,
+                _ => 1.0
+            };
+            let balance = match self.white_balance[index] {
+                balance if balance.is_finite() => balance.clamp(0.0, 1.0),
+                _ => 1.0
+            };
+
+            let level = (f32::from(value.min(127)) / 127.0).powf(gamma) * balance;
+            (level * 127.0).round().clamp(0.0, 127.0) as u8
+        };
+
+        LaunchpadColor { red: channel(color.red, 0), green: channel(color.green, 1), blue: channel(color.blue, 2) }
+    }
+}
+
+impl Default for ColorProfile {
+    /// A profile that leaves colors as they are.
+    fn default() -> ColorProfile {
+        ColorProfile::with_gamma(1.0)
+    }
+}
+
+/// How a light shows its color.
+///
+/// The palette modes use the device's built-in palette of 128 colors, and
+/// are animated by the device itself in time with its clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LightMode {
+    /// The light's RGB color.
+    #[default]
+    Rgb,
+    /// A static palette color.
+    Palette(u8),
+    /// Flashing between two palette colors.
+    Flash { a: u8, b: u8 },
+    /// Pulsing a palette color.
+    Pulse(u8)
+}
+
+impl LightMode {
+    /// Checks that the palette colors this mode uses are in the palette.
+    pub fn validate(&self) -> Result<(), LaunchpadError> {
+        match *self {
+            LightMode::Palette(index) | LightMode::Pulse(index) if index > 127 => Err(LaunchpadError::InvalidPaletteIndex(index)),
+            LightMode::Flash { a, .. } if a > 127 => Err(LaunchpadError::InvalidPaletteIndex(a)),
+            LightMode::Flash { b, .. } if b > 127 => Err(LaunchpadError::InvalidPaletteIndex(b)),
+            _ => Ok(())
+        }
+    }
+}
+
+/// The CSS named colors, sorted by name for lookup.
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_and_names() {
+        let color = LaunchpadColor { red: 0, green: 43, blue: 53 };
+        assert_eq!(LaunchpadColor::from_hex("#00576b"), Some(color));
+        assert_eq!(LaunchpadColor::from_hex("00576B"), Some(color));
+        assert_eq!(LaunchpadColor::from_hex("#f00"), Some(LaunchpadColor::RED));
+        assert_eq!(LaunchpadColor::from_hex("#+0576b"), None);
+        assert_eq!(LaunchpadColor::from_hex("#0576b"), None);
+
+        assert_eq!(LaunchpadColor::from_name("Lime"), Some(LaunchpadColor::GREEN));
+        assert_eq!(LaunchpadColor::from_name("teal"), Some(LaunchpadColor::from_rgb8(0, 128, 128)));
+        assert_eq!(LaunchpadColor::from_name("not a color"), None);
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn mixes_colors() {
+        assert_eq!(LaunchpadColor::from_hsv(0.0, 1.0, 1.0), LaunchpadColor::RED);
+        assert_eq!(LaunchpadColor::from_hsv(240.0, 1.0, 1.0), LaunchpadColor::BLUE);
+        assert_eq!(LaunchpadColor::from_hsv(-240.0, 1.0, 0.5), LaunchpadColor { red: 0, green: 64, blue: 0 });
+
+        assert_eq!(LaunchpadColor::RED.lerp(LaunchpadColor::BLUE, 0.5), LaunchpadColor { red: 64, green: 0, blue: 64 });
+        assert_eq!(LaunchpadColor::RED.lerp(LaunchpadColor::BLUE, 2.0), LaunchpadColor::BLUE);
+        assert_eq!(LaunchpadColor::RED.scale(0.5), LaunchpadColor { red: 64, green: 0, blue: 0 });
+        assert_eq!(LaunchpadColor::RED.scale(3.0), LaunchpadColor::RED);
+    }
+
+    #[test]
+    fn applies_color_profiles() {
+        let color = LaunchpadColor { red: 64, green: 127, blue: 0 };
+        assert_eq!(ColorProfile::default().apply(color), color);
+        assert_eq!(ColorProfile::with_gamma(2.0).apply(color), LaunchpadColor { red: 32, green: 127, blue: 0 });
+
+        let profile = ColorProfile { gamma: [1.0; 3], white_balance: [1.0, 0.5, 1.0] };
+        assert_eq!(profile.apply(color), LaunchpadColor { red: 64, green: 64, blue: 0 });
+    }
+
+    #[test]
+    fn ignores_invalid_profile_values() {
+        let color = LaunchpadColor { red: 64, green: 127, blue: 0 };
+        assert_eq!(ColorProfile::with_gamma(0.0).apply(color), color);
+        assert_eq!(ColorProfile::with_gamma(-2.0).apply(LaunchpadColor::BLACK), LaunchpadColor::BLACK);
+        assert_eq!(ColorProfile::with_gamma(f32::NAN).apply(color), color);
+
+        let profile = ColorProfile { gamma: [1.0; 3], white_balance: [f32::INFINITY, 2.0, -1.0] };
+        assert_eq!(profile.apply(LaunchpadColor { red: 127, green: 127, blue: 127 }), LaunchpadColor { red: 127, green: 127, blue: 0 });
+    }
+}
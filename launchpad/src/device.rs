@@ -0,0 +1,256 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use regex::Regex;
+
+use crate::Launchpad;
+use crate::error::LaunchpadError;
+use crate::event::{LaunchpadEvent, LaunchpadEventArgs};
+use crate::protocol;
+use crate::transport::MidiTransport;
+use crate::launchpad_x::LaunchpadX;
+use crate::launchpad_mini_mk3::LaunchpadMiniMk3;
+use crate::launchpad_pro_mk3::LaunchpadProMk3;
+use crate::launchpad_mk2::LaunchpadMk2;
+use crate::launchpad_s::LaunchpadS;
+
+/// Universal SysEx Device Inquiry, answered by every Novation Launchpad.
+pub const DEVICE_INQUIRY: [u8; 6] = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LaunchpadModel {
+    X,
+    MiniMk3,
+    ProMk3,
+    Mk2,
+    S,
+    MiniMk2
+}
+
+impl LaunchpadModel {
+    /// Matches the name of the port this model takes lighting messages on.
+    pub fn port_pattern(self) -> &'static str {
+        match self {
+            LaunchpadModel::X => LaunchpadX::PORT_PATTERN,
+            LaunchpadModel::MiniMk3 => LaunchpadMiniMk3::PORT_PATTERN,
+            LaunchpadModel::ProMk3 => LaunchpadProMk3::PORT_PATTERN,
+            LaunchpadModel::Mk2 => LaunchpadMk2::PORT_PATTERN,
+            LaunchpadModel::S | LaunchpadModel::MiniMk2 => LaunchpadS::PORT_PATTERN
+        }
+    }
+
+    /// Whether `name` is the port to drive this model through.
+    ///
+    /// The MK3 generation also answers the Device Inquiry on its DAW port, so
+    /// for those models only the port matching `port_pattern` counts. Older
+    /// models only have one port.
+    pub fn is_control_port(self, name: &str) -> bool {
+        match self {
+            LaunchpadModel::X | LaunchpadModel::MiniMk3 | LaunchpadModel::ProMk3 => {
+                Regex::new(self.port_pattern()).unwrap().is_match(name)
+            }
+            LaunchpadModel::Mk2 | LaunchpadModel::S | LaunchpadModel::MiniMk2 => true
+        }
+    }
+
+    /// Guesses the model from a MIDI port name, for devices that do not answer
+    /// the Device Inquiry.
+    pub fn from_port_name(name: &str) -> Option<LaunchpadModel> {
+        // the Mini MK3 comes first, as the Launchpad S pattern also matches older Minis.
+        let models = [
+            LaunchpadModel::MiniMk3,
+            LaunchpadModel::ProMk3,
+            LaunchpadModel::X,
+            LaunchpadModel::Mk2,
+            LaunchpadModel::S
+        ];
+
+        models.iter()
+            .find(|model| Regex::new(model.port_pattern()).unwrap().is_match(name))
+            .copied()
+    }
+
+    /// Builds the backend for this model on top of `transport`. If the device
+    /// has already answered the Device Inquiry, pass its `info` so it is not
+    /// asked again.
+    pub fn open(self, transport: Box<dyn MidiTransport>, info: Option<DeviceInfo>) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        Ok(match self {
+            LaunchpadModel::X => Box::new(LaunchpadX::with_transport_and_info(transport, info)?),
+            LaunchpadModel::MiniMk3 => Box::new(LaunchpadMiniMk3::with_transport_and_info(transport, info)?),
+            LaunchpadModel::ProMk3 => Box::new(LaunchpadProMk3::with_transport_and_info(transport, info)?),
+            LaunchpadModel::Mk2 => Box::new(LaunchpadMk2::with_transport_and_info(transport, info)?),
+            LaunchpadModel::S | LaunchpadModel::MiniMk2 => Box::new(LaunchpadS::with_transport_and_info(transport, info)?)
+        })
+    }
+}
+
+/// What a Launchpad reports about itself in reply to `DEVICE_INQUIRY`.
+///
+/// A device answers either from its application or from its bootloader, so
+/// exactly one of the two versions is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub model: LaunchpadModel,
+    pub firmware_revision: Option<u32>,
+    pub bootloader_version: Option<u32>
+}
+
+impl DeviceInfo {
+    /// Parses a Device Inquiry reply:
+    /// `F0 7E <id> 06 02 00 20 29 <family> <member> <version> F7`.
+    pub fn parse(message: &[u8]) -> Option<DeviceInfo> {
+        match message {
+            [0xF0, 0x7E, _, 0x06, 0x02, 0x00, 0x20, 0x29, family, 0x00 | 0x01, _, _, v0, v1, v2, v3, 0xF7] => {
+                // each version byte holds one decimal digit.
+                let version = [v0, v1, v2, v3].iter().fold(0, |acc, digit| acc * 10 + **digit as u32);
+
+                let (model, bootloader) = match family {
+                    0x03 => (LaunchpadModel::X, false),
+                    0x11 => (LaunchpadModel::X, true),
+                    0x13 => (LaunchpadModel::MiniMk3, false),
+                    0x17 => (LaunchpadModel::MiniMk3, true),
+                    0x23 => (LaunchpadModel::ProMk3, false),
+                    0x69 => (LaunchpadModel::Mk2, false),
+                    0x20 => (LaunchpadModel::S, false),
+                    0x36 => (LaunchpadModel::MiniMk2, false),
+                    _ => return None
+                };
+
+                Some(DeviceInfo {
+                    model,
+                    firmware_revision: if bootloader { None } else { Some(version) },
+                    bootloader_version: if bootloader { Some(version) } else { None }
+                })
+            }
+            _ => None
+        }
+    }
+}
+
+/// A connected Launchpad found by `list_launchpads`, which `open` can connect to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchpadDescriptor {
+    pub port_name: String,
+    pub info: DeviceInfo
+}
+
+impl LaunchpadDescriptor {
+    pub fn model(&self) -> LaunchpadModel {
+        self.info.model
+    }
+}
+
+/// Sends a Device Inquiry over `transport` and waits up to `timeout` for a
+/// Launchpad to answer.
+///
+/// This replaces the transport's listener, so call it before building a backend.
+pub fn identify(transport: &mut dyn MidiTransport, timeout: Duration) -> Result<DeviceInfo, LaunchpadError> {
+    let (sender, receiver) = mpsc::channel();
+    transport.listen(Box::new(move |_stamp, message| {
+        if let Some(info) = DeviceInfo::parse(message) {
+            let _ = sender.send(info);
+        }
+    }));
+
+    transport.send(&DEVICE_INQUIRY)?;
+
+    receiver.recv_timeout(timeout)
+        .map_err(|_| LaunchpadError::Timeout("no reply to device inquiry".to_string()))
+}
+
+/// The event a backend triggers, and where it keeps the device's `DeviceInfo`.
+pub(crate) type Listener = (Arc<Mutex<LaunchpadEvent>>, Arc<Mutex<Option<DeviceInfo>>>);
+
+/// Starts delivering what the device sends as events, parsed by `parse`.
+/// The info starts out as `known`, and is filled in when the device answers
+/// the Device Inquiry sent by `verify`.
+pub(crate) fn listen(
+    transport: &mut dyn MidiTransport,
+    known: Option<DeviceInfo>,
+    parse: fn(&[u8]) -> Option<LaunchpadEventArgs>
+) -> Listener {
+    let event = Arc::new(Mutex::new(LaunchpadEvent::default()));
+    let info = Arc::new(Mutex::new(known));
+    protocol::listen(transport, event.clone(), info.clone(), parse);
+
+    (event, info)
+}
+
+/// Sends the Device Inquiry, unless the device has already been identified
+/// as `known`. The reply arrives through the listener started by `listen`.
+pub(crate) fn verify(transport: &mut dyn MidiTransport, known: Option<DeviceInfo>) -> Result<(), LaunchpadError> {
+    match known {
+        Some(_) => Ok(()),
+        None => transport.send(&DEVICE_INQUIRY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::LoopbackTransport;
+
+    use std::thread;
+
+    const LAUNCHPAD_X_REPLY: [u8; 17] = [0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0x03, 0x01, 0x00, 0x00, 0x00, 0x04, 0x02, 0x07, 0xF7];
+
+    #[test]
+    fn parses_inquiry_reply() {
+        assert_eq!(DeviceInfo::parse(&LAUNCHPAD_X_REPLY), Some(DeviceInfo {
+            model: LaunchpadModel::X,
+            firmware_revision: Some(427),
+            bootloader_version: None
+        }));
+
+        let mut bootloader = LAUNCHPAD_X_REPLY;
+        bootloader[8] = 0x11;
+        assert_eq!(DeviceInfo::parse(&bootloader), Some(DeviceInfo {
+            model: LaunchpadModel::X,
+            firmware_revision: None,
+            bootloader_version: Some(427)
+        }));
+
+        assert_eq!(DeviceInfo::parse(&[0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0x7F, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0xF7]), None);
+        assert_eq!(DeviceInfo::parse(&DEVICE_INQUIRY), None);
+    }
+
+    #[test]
+    fn identifies_over_transport() {
+        let mut transport = LoopbackTransport::new();
+
+        let device = transport.clone();
+        let responder = thread::spawn(move || {
+            while device.sent().is_empty() {
+                thread::yield_now();
+            }
+            device.receive(&LAUNCHPAD_X_REPLY);
+        });
+
+        let info = identify(&mut transport, Duration::from_secs(5)).unwrap();
+        responder.join().unwrap();
+
+        assert_eq!(transport.sent(), vec![DEVICE_INQUIRY.to_vec()]);
+        assert_eq!(info.model, LaunchpadModel::X);
+        assert!(matches!(identify(&mut LoopbackTransport::new(), Duration::from_millis(10)), Err(LaunchpadError::Timeout(_))));
+
+        // the backend keeps the reply rather than asking again.
+        let launchpad = info.model.open(Box::new(transport.clone()), Some(info)).unwrap();
+        assert_eq!(launchpad.device_info(), Some(info));
+        assert_eq!(transport.sent().iter().filter(|message| **message == DEVICE_INQUIRY).count(), 1);
+    }
+
+    #[test]
+    fn guesses_model_from_port_name() {
+        assert_eq!(LaunchpadModel::from_port_name("Launchpad Mini MK3:Launchpad Mini MK3 MIDI 2 24:1"), Some(LaunchpadModel::MiniMk3));
+        assert_eq!(LaunchpadModel::from_port_name("Launchpad Mini:Launchpad Mini MIDI 1 20:0"), Some(LaunchpadModel::S));
+        assert_eq!(LaunchpadModel::from_port_name("Midi Through Port-0"), None);
+    }
+
+    #[test]
+    fn skips_daw_ports() {
+        assert!(LaunchpadModel::X.is_control_port("Launchpad X:Launchpad X MIDI 2 20:1"));
+        assert!(!LaunchpadModel::X.is_control_port("Launchpad X:Launchpad X MIDI 1 20:0"));
+        assert!(!LaunchpadModel::ProMk3.is_control_port("Launchpad Pro MK3:Launchpad Pro MK3 LPProMK3 DAW 20:2"));
+        assert!(LaunchpadModel::Mk2.is_control_port("Launchpad MK2:Launchpad MK2 MIDI 1 20:0"));
+    }
+}
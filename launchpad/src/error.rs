@@ -0,0 +1,71 @@
+use std::fmt;
+
+use crate::button::Button;
+use crate::color::LightMode;
+
+#[derive(Debug)]
+pub enum LaunchpadError {
+    /// No MIDI ports were available at all.
+    NoPorts,
+    /// Ports were available, but none of them could be selected.
+    PortNotFound(String),
+    ConnectFailed(String),
+    /// A message was not delivered to the device.
+    SendFailed(String),
+    InvalidCoordinate { x: usize, y: usize },
+    /// The device has no such button.
+    InvalidButton(Button),
+    /// The device cannot show lights in this mode.
+    UnsupportedLightMode(LightMode),
+    /// A light mode used a color outside the 128 color palette.
+    InvalidPaletteIndex(u8),
+    /// The device has no such setting, such as brightness or sleep mode.
+    UnsupportedFeature(String),
+    UnsupportedDevice(String),
+    /// The device did not answer a query in time.
+    Timeout(String)
+}
+
+impl fmt::Display for LaunchpadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LaunchpadError::NoPorts => write!(f, "no MIDI ports found"),
+            LaunchpadError::PortNotFound(reason) => write!(f, "no suitable MIDI port found: {}", reason),
+            LaunchpadError::ConnectFailed(reason) => write!(f, "failed to connect to MIDI port: {}", reason),
+            LaunchpadError::SendFailed(reason) => write!(f, "failed to send MIDI message: {}", reason),
+            LaunchpadError::InvalidCoordinate { x, y } => write!(f, "coordinate ({}, {}) is outside the grid", x, y),
+            LaunchpadError::InvalidButton(button) => write!(f, "the device has no {:?} button", button),
+            LaunchpadError::UnsupportedLightMode(mode) => write!(f, "the device cannot show {:?} lights", mode),
+            LaunchpadError::InvalidPaletteIndex(index) => write!(f, "palette index {} is outside the palette", index),
+            LaunchpadError::UnsupportedFeature(feature) => write!(f, "the device does not support {}", feature),
+            LaunchpadError::UnsupportedDevice(name) => write!(f, "unsupported device: {}", name),
+            LaunchpadError::Timeout(reason) => write!(f, "timed out: {}", reason)
+        }
+    }
+}
+
+impl std::error::Error for LaunchpadError {}
+
+impl From<midir::InitError> for LaunchpadError {
+    fn from(err: midir::InitError) -> LaunchpadError {
+        LaunchpadError::ConnectFailed(err.to_string())
+    }
+}
+
+impl<T> From<midir::ConnectError<T>> for LaunchpadError {
+    fn from(err: midir::ConnectError<T>) -> LaunchpadError {
+        LaunchpadError::ConnectFailed(err.to_string())
+    }
+}
+
+impl From<midir::PortInfoError> for LaunchpadError {
+    fn from(err: midir::PortInfoError) -> LaunchpadError {
+        LaunchpadError::PortNotFound(err.to_string())
+    }
+}
+
+impl From<midir::SendError> for LaunchpadError {
+    fn from(err: midir::SendError) -> LaunchpadError {
+        LaunchpadError::SendFailed(err.to_string())
+    }
+}
@@ -1,25 +1,474 @@
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use crate::button::Button;
+
+/// Pad events carry both the grid position of the pad and the `Button` it is
+/// on the device's `ButtonLayout`, so handlers can match on either.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LaunchpadEventArgs {
-    Released { x: usize, y: usize },
-    Pressed { x: usize, y: usize },
+    Released { x: usize, y: usize, button: Button },
+    /// A pad was pressed with a velocity from 1 to 127. Devices without
+    /// velocity sensitive pads always report 127.
+    Pressed { x: usize, y: usize, button: Button, velocity: u8 },
+    /// The pressure on a held pad changed (polyphonic aftertouch).
+    Aftertouch { x: usize, y: usize, button: Button, pressure: u8 },
+    /// The pressure on the pads as a whole changed (channel pressure).
+    ChannelPressure { pressure: u8 },
+    /// A pad was pressed and released quickly, with no second press following.
+    Tap { x: usize, y: usize, button: Button },
+    /// A pad was tapped twice in quick succession.
+    DoubleTap { x: usize, y: usize, button: Button },
+    /// A pad has been held for the long press time.
+    LongPress { x: usize, y: usize, button: Button },
+    /// A pad is still held, repeated `count` times so far.
+    HoldRepeat { x: usize, y: usize, button: Button, count: u32 },
+    /// A pad was pressed while others were held. `buttons` lists every held
+    /// button in the order they were pressed, ending with the new one.
+    Chord { buttons: Vec<Button> },
+    /// Pads were pressed one after another along a row or column.
+    Swipe { direction: SwipeDirection, from: Button, to: Button, length: usize },
+    /// A watched device came back and has been reopened.
+    Connected,
+    /// A watched device went away.
+    Disconnected,
+    /// A message from the device that is not a pad event, such as a SysEx
+    /// reply, clock or program change.
+    Raw(Vec<u8>),
+}
+
+/// Which way a swipe went, in grid coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SwipeDirection {
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+impl SwipeDirection {
+    /// The direction of a step from one pad to a neighbouring one.
+    pub(crate) fn between(from: (usize, usize), to: (usize, usize)) -> Option<SwipeDirection> {
+        match (to.0 as isize - from.0 as isize, to.1 as isize - from.1 as isize) {
+            (0, -1) => Some(SwipeDirection::Up),
+            (0, 1) => Some(SwipeDirection::Down),
+            (-1, 0) => Some(SwipeDirection::Left),
+            (1, 0) => Some(SwipeDirection::Right),
+            _ => None
+        }
+    }
+}
+
+/// When an event happened, given to `LaunchpadEventHandler::notify_at`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventTime {
+    /// When the MIDI message behind the event arrived, in microseconds, as
+    /// given by the MIDI driver. Stamps only mean something relative to other
+    /// stamps from the same device. `None` for events not caused by a message.
+    pub stamp: Option<u64>,
+    /// When the event was triggered.
+    pub instant: Instant
+}
+
+impl EventTime {
+    pub fn now() -> EventTime {
+        EventTime { stamp: None, instant: Instant::now() }
+    }
+
+    /// The time of a MIDI message that arrived at `stamp`.
+    pub fn at(stamp: u64) -> EventTime {
+        EventTime { stamp: Some(stamp), instant: Instant::now() }
+    }
+}
+
+/// An event together with when it happened, as delivered by
+/// `LaunchpadEvent::channel` and the event streams.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub args: LaunchpadEventArgs,
+    pub time: EventTime
+}
+
+impl LaunchpadEventArgs {
+    /// The grid position of the pad this event is about, if it is about one pad.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match *self {
+            LaunchpadEventArgs::Released { x, y, .. }
+            | LaunchpadEventArgs::Pressed { x, y, .. }
+            | LaunchpadEventArgs::Aftertouch { x, y, .. }
+            | LaunchpadEventArgs::Tap { x, y, .. }
+            | LaunchpadEventArgs::DoubleTap { x, y, .. }
+            | LaunchpadEventArgs::LongPress { x, y, .. }
+            | LaunchpadEventArgs::HoldRepeat { x, y, .. } => Some((x, y)),
+            _ => None
+        }
+    }
+
+    /// The button this event is about, if it is about one pad.
+    pub fn button(&self) -> Option<Button> {
+        match *self {
+            LaunchpadEventArgs::Released { button, .. }
+            | LaunchpadEventArgs::Pressed { button, .. }
+            | LaunchpadEventArgs::Aftertouch { button, .. }
+            | LaunchpadEventArgs::Tap { button, .. }
+            | LaunchpadEventArgs::DoubleTap { button, .. }
+            | LaunchpadEventArgs::LongPress { button, .. }
+            | LaunchpadEventArgs::HoldRepeat { button, .. } => Some(button),
+            _ => None
+        }
+    }
+}
+
+/// Limits a subscription to the events of some pads. Events that are not
+/// about a single pad, such as `Chord` or `Connected`, never pass a filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventFilter {
+    /// Pads inside the box at `(x, y)` with this width and height.
+    Region { x: usize, y: usize, width: usize, height: usize },
+    /// The 8x8 grid pads.
+    Grid,
+    /// Every button that is not a grid pad.
+    Controls,
+    /// Exactly these buttons.
+    Buttons(Vec<Button>)
+}
+
+impl EventFilter {
+    pub fn matches(&self, args: &LaunchpadEventArgs) -> bool {
+        match self {
+            EventFilter::Region { x, y, width, height } => args.position()
+                .is_some_and(|(px, py)| px >= *x && px < x.saturating_add(*width)
+                    && py >= *y && py < y.saturating_add(*height)),
+            EventFilter::Grid => matches!(args.button(), Some(Button::Grid { .. })),
+            EventFilter::Controls => args.button().is_some_and(|button| !matches!(button, Button::Grid { .. })),
+            EventFilter::Buttons(buttons) => args.button().is_some_and(|button| buttons.contains(&button))
+        }
+    }
+}
+
+/// Keeps a handler subscribed by `subscribe_fn` or `subscribe_filtered`.
+///
+/// Dropping it, or calling `unsubscribe`, stops the handler from being
+/// called and frees it, along with anything it holds. A handler that is
+/// running at the time is freed as soon as it returns.
+#[must_use = "dropping a Subscription unsubscribes its handler"]
+pub struct Subscription {
+    subscriber: Arc<Subscriber>
+}
+
+impl Subscription {
+    pub fn unsubscribe(self) {}
+
+    /// Keeps the handler subscribed for as long as the event exists.
+    pub fn detach(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.subscriber.release();
+    }
+}
+
+struct Subscriber {
+    handler: Mutex<Option<Box<dyn LaunchpadEventHandler>>>,
+    active: AtomicBool
+}
+
+impl Subscriber {
+    fn release(&self) {
+        self.active.store(false, Ordering::SeqCst);
+
+        // the lock is only held while the handler runs, in which case
+        // `trigger_timed` frees it once it returns.
+        if let Ok(mut handler) = self.handler.try_lock() {
+            handler.take();
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct LaunchpadEvent {
-    callbacks: Vec<Box<dyn LaunchpadEventHandler>>
+    callbacks: Vec<Arc<Subscriber>>
 }
 
 impl LaunchpadEvent {
     pub fn subscribe(&mut self, handler: Box<dyn LaunchpadEventHandler>) {
-        self.callbacks.push(handler);
+        self.add(handler);
+    }
+
+    /// Like `subscribe`, until the returned `Subscription` is dropped.
+    pub fn subscribe_handler(&mut self, handler: Box<dyn LaunchpadEventHandler>) -> Subscription {
+        Subscription { subscriber: self.add(handler) }
+    }
+
+    /// Subscribes a closure until the returned `Subscription` is dropped. It
+    /// is called with each event and when it happened.
+    pub fn subscribe_fn<F>(&mut self, callback: F) -> Subscription
+    where F: Fn(&LaunchpadEventArgs, EventTime) + Send + Sync + 'static {
+        Subscription { subscriber: self.add(Box::new(FnHandler { callback, filter: None })) }
+    }
+
+    /// Like `subscribe_fn`, but only for the events that pass `filter`.
+    pub fn subscribe_filtered<F>(&mut self, filter: EventFilter, callback: F) -> Subscription
+    where F: Fn(&LaunchpadEventArgs, EventTime) + Send + Sync + 'static {
+        Subscription { subscriber: self.add(Box::new(FnHandler { callback, filter: Some(filter) })) }
+    }
+
+    fn add(&mut self, handler: Box<dyn LaunchpadEventHandler>) -> Arc<Subscriber> {
+        // unsubscribed handlers are already freed; this drops their slots.
+        self.callbacks.retain(|subscriber| subscriber.active.load(Ordering::SeqCst));
+
+        let subscriber = Arc::new(Subscriber { handler: Mutex::new(Some(handler)), active: AtomicBool::new(true) });
+        self.callbacks.push(subscriber.clone());
+        subscriber
+    }
+
+    /// How many handlers are still subscribed.
+    #[cfg(test)]
+    pub(crate) fn subscriber_count(&self) -> usize {
+        self.callbacks.iter().filter(|subscriber| subscriber.active.load(Ordering::SeqCst)).count()
     }
 
     pub fn trigger(&self, args: LaunchpadEventArgs) {
-        for handler in self.callbacks.iter() {
-            handler.notify(&args);
+        self.trigger_timed(args, EventTime::now());
+    }
+
+    /// Returns a receiver that gets every event triggered from now on, as an
+    /// alternative to subscribing a handler. Events stop arriving once this
+    /// `LaunchpadEvent` is dropped, which ends iteration over the receiver.
+    /// Dropping the receiver unsubscribes it at the next event.
+    pub fn channel(&mut self) -> mpsc::Receiver<TimedEvent> {
+        let (sender, receiver) = mpsc::channel();
+        let slot = Arc::new(Mutex::new(Weak::new()));
+        let subscriber = self.add(Box::new(ChannelHandler { sender, subscriber: slot.clone() }));
+        *slot.lock().unwrap() = Arc::downgrade(&subscriber);
+        receiver
+    }
+
+    /// Triggers an event that came from a MIDI message received at `stamp`.
+    pub fn trigger_at(&self, args: LaunchpadEventArgs, stamp: u64) {
+        self.trigger_timed(args, EventTime::at(stamp));
+    }
+
+    /// Triggers an event that happened at `time`, such as one passed on from
+    /// another `LaunchpadEvent`.
+    pub fn trigger_timed(&self, args: LaunchpadEventArgs, time: EventTime) {
+        for subscriber in self.callbacks.iter() {
+            if !subscriber.active.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            if let Some(handler) = subscriber.handler.lock().unwrap().as_ref() {
+                handler.notify_at(&args, time);
+            }
+
+            // the handler may have been unsubscribed while it ran, when
+            // `release` could not free it.
+            if !subscriber.active.load(Ordering::SeqCst) {
+                if let Ok(mut handler) = subscriber.handler.try_lock() {
+                    handler.take();
+                }
+            }
         }
     }
 }
 
 pub trait LaunchpadEventHandler: Send + Sync {
     fn notify(&self, event: &LaunchpadEventArgs);
+
+    /// Called for every event with when it happened. Calls `notify` unless
+    /// the handler needs the time.
+    fn notify_at(&self, event: &LaunchpadEventArgs, _time: EventTime) {
+        self.notify(event);
+    }
+}
+
+/// Passes events on to a channel made by `LaunchpadEvent::channel`.
+struct ChannelHandler {
+    sender: mpsc::Sender<TimedEvent>,
+    // weak, as the subscriber owns this handler.
+    subscriber: Arc<Mutex<Weak<Subscriber>>>
+}
+
+impl LaunchpadEventHandler for ChannelHandler {
+    fn notify(&self, args: &LaunchpadEventArgs) {
+        self.notify_at(args, EventTime::now());
+    }
+
+    fn notify_at(&self, args: &LaunchpadEventArgs, time: EventTime) {
+        // sending only fails once the receiver is dropped, so nothing would
+        // ever read the events again.
+        if self.sender.send(TimedEvent { args: args.clone(), time }).is_err() {
+            if let Some(subscriber) = self.subscriber.lock().unwrap().upgrade() {
+                subscriber.release();
+            }
+        }
+    }
+}
+
+/// Calls a closure subscribed by `subscribe_fn` or `subscribe_filtered`.
+struct FnHandler<F> {
+    callback: F,
+    filter: Option<EventFilter>
+}
+
+impl<F> LaunchpadEventHandler for FnHandler<F>
+where F: Fn(&LaunchpadEventArgs, EventTime) + Send + Sync {
+    fn notify(&self, args: &LaunchpadEventArgs) {
+        self.notify_at(args, EventTime::now());
+    }
+
+    fn notify_at(&self, args: &LaunchpadEventArgs, time: EventTime) {
+        let matches = match self.filter {
+            Some(ref filter) => filter.matches(args),
+            None => true
+        };
+
+        if matches {
+            (self.callback)(args, time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Barrier, Mutex};
+    use std::thread;
+
+    fn pressed(x: usize, y: usize) -> LaunchpadEventArgs {
+        LaunchpadEventArgs::Pressed { x, y, button: Button::Grid { x, y: y - 1 }, velocity: 127 }
+    }
+
+    #[test]
+    fn unsubscribes_when_dropped() {
+        let mut event = LaunchpadEvent::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = events.clone();
+        let subscription = event.subscribe_fn(move |args, _| recorded.lock().unwrap().push(args.clone()));
+
+        event.trigger(pressed(1, 1));
+        drop(subscription);
+        event.trigger(pressed(2, 2));
+
+        assert_eq!(*events.lock().unwrap(), vec![pressed(1, 1)]);
+
+        event.subscribe_fn(|_, _| ()).detach();
+        assert_eq!(event.callbacks.len(), 1);
+    }
+
+    #[test]
+    fn frees_handlers_when_unsubscribed() {
+        let mut event = LaunchpadEvent::default();
+
+        let held = Arc::new(());
+        let captured = held.clone();
+        let subscription = event.subscribe_fn(move |_, _| { let _ = &captured; });
+        drop(subscription);
+        assert_eq!(Arc::strong_count(&held), 1);
+
+        // a handler dropping its own subscription is freed once it returns.
+        let slot = Arc::new(Mutex::new(None));
+        let captured = (held.clone(), slot.clone());
+        *slot.lock().unwrap() = Some(event.subscribe_fn(move |_, _| {
+            let _ = &captured.0;
+            captured.1.lock().unwrap().take();
+        }));
+        assert_eq!(Arc::strong_count(&held), 2);
+        event.trigger(pressed(1, 1));
+        assert_eq!(Arc::strong_count(&held), 1);
+    }
+
+    #[test]
+    fn frees_handlers_unsubscribed_from_another_thread() {
+        let mut event = LaunchpadEvent::default();
+
+        let held = Arc::new(());
+        let running = Arc::new(Barrier::new(2));
+        let resume = Arc::new(Barrier::new(2));
+        let captured = (held.clone(), running.clone(), resume.clone());
+        let subscription = event.subscribe_fn(move |_, _| {
+            let _ = &captured.0;
+            captured.1.wait();
+            captured.2.wait();
+        });
+
+        let trigger = thread::spawn(move || event.trigger(pressed(1, 1)));
+
+        // unsubscribe while the handler is running.
+        running.wait();
+        drop(subscription);
+        resume.wait();
+
+        trigger.join().unwrap();
+        assert_eq!(Arc::strong_count(&held), 1);
+    }
+
+    #[test]
+    fn unsubscribes_dropped_channels() {
+        let mut event = LaunchpadEvent::default();
+        let events = event.channel();
+        drop(event.channel());
+
+        event.trigger(pressed(1, 1));
+        assert_eq!(event.subscriber_count(), 1);
+        assert!(event.callbacks[1].handler.lock().unwrap().is_none());
+        assert_eq!(events.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn filters_by_region_and_button() {
+        let mut event = LaunchpadEvent::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = events.clone();
+        let _region = event.subscribe_filtered(EventFilter::Region { x: 0, y: 1, width: 2, height: 2 }, move |args, _| {
+            recorded.lock().unwrap().push(args.clone());
+        });
+        let recorded = events.clone();
+        let _scene = event.subscribe_filtered(EventFilter::Buttons(vec![Button::Scene(0)]), move |args, _| {
+            recorded.lock().unwrap().push(args.clone());
+        });
+
+        event.trigger(pressed(1, 2));
+        event.trigger(pressed(2, 2));
+        event.trigger(LaunchpadEventArgs::Released { x: 8, y: 1, button: Button::Scene(0) });
+        event.trigger(LaunchpadEventArgs::ChannelPressure { pressure: 10 });
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            pressed(1, 2),
+            LaunchpadEventArgs::Released { x: 8, y: 1, button: Button::Scene(0) }
+        ]);
+        assert!(EventFilter::Controls.matches(&LaunchpadEventArgs::Released { x: 8, y: 1, button: Button::Scene(0) }));
+        assert!(!EventFilter::Grid.matches(&LaunchpadEventArgs::Connected));
+    }
+
+    #[test]
+    fn unbounded_regions_reach_the_edge() {
+        let region = EventFilter::Region { x: 2, y: 3, width: usize::MAX, height: usize::MAX };
+        assert!(region.matches(&pressed(8, 8)));
+        assert!(!region.matches(&pressed(1, 8)));
+    }
+
+    #[test]
+    fn passes_on_event_times() {
+        let mut event = LaunchpadEvent::default();
+        let events = event.channel();
+
+        let times = Arc::new(Mutex::new(Vec::new()));
+        let recorded = times.clone();
+        let _subscription = event.subscribe_fn(move |_, time| recorded.lock().unwrap().push(time.stamp));
+
+        event.trigger_at(pressed(1, 1), 1500);
+        event.trigger(pressed(2, 2));
+
+        assert_eq!(*times.lock().unwrap(), vec![Some(1500), None]);
+        let received = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(received.iter().map(|event| event.args.clone()).collect::<Vec<_>>(), vec![pressed(1, 1), pressed(2, 2)]);
+        assert_eq!(received[0].time.stamp, Some(1500));
+    }
 }
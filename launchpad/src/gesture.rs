@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::Launchpad;
+use crate::button::Button;
+use crate::event::*;
+
+/// Thresholds for recognising gestures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// How long a pad must be held to count as a long press.
+    pub long_press: Duration,
+    /// How soon a second tap must follow the first to count as a double tap.
+    /// A tap is only reported once this has passed without a second press.
+    pub double_tap: Duration,
+    /// How long a pad must be held before it starts repeating.
+    pub repeat_delay: Duration,
+    /// How often a held pad repeats.
+    pub repeat_interval: Duration,
+    /// How soon each pad of a swipe must follow the one before.
+    pub swipe_interval: Duration,
+    /// How many pads in a row make a swipe.
+    pub swipe_length: usize
+}
+
+impl Default for GestureConfig {
+    fn default() -> GestureConfig {
+        GestureConfig {
+            long_press: Duration::from_millis(500),
+            double_tap: Duration::from_millis(300),
+            repeat_delay: Duration::from_millis(500),
+            repeat_interval: Duration::from_millis(100),
+            swipe_interval: Duration::from_millis(150),
+            swipe_length: 3
+        }
+    }
+}
+
+/// Turns the presses and releases of a `Launchpad` into gesture events,
+/// triggered on the detector's own event.
+///
+/// Each pad reports `Tap`, `DoubleTap`, `LongPress` and `HoldRepeat`. Holding
+/// a pad reports a `LongPress` once, then a `HoldRepeat` every
+/// `repeat_interval`. Across pads, pressing a pad while others are held
+/// reports a `Chord`, and pressing neighbouring pads one after another along
+/// a row or column reports a `Swipe` once the last one is pressed. As fingers
+/// usually overlap while swiping, a swipe may also report chords.
+///
+/// Gestures are timed with the stamps of the MIDI messages, so they are not
+/// thrown off by a busy handler.
+pub struct GestureDetector {
+    event: Arc<Mutex<LaunchpadEvent>>,
+    recognizer: Arc<Mutex<Recognizer>>,
+    input: Option<Subscription>,
+
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl GestureDetector {
+    /// How often held pads and pending taps are checked.
+    pub const TICK: Duration = Duration::from_millis(10);
+
+    pub fn new(launchpad: &dyn Launchpad, config: GestureConfig) -> GestureDetector {
+        let event = Arc::new(Mutex::new(LaunchpadEvent::default()));
+        let recognizer = Arc::new(Mutex::new(Recognizer::new(config)));
+        let clock = Arc::new(Mutex::new(Clock::new()));
+
+        let input = launchpad.get_event().lock().unwrap().subscribe_handler(Box::new(Input {
+            recognizer: recognizer.clone(),
+            clock: clock.clone(),
+            event: event.clone()
+        }));
+
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let event = event.clone();
+            let recognizer = recognizer.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    thread::sleep(GestureDetector::TICK);
+
+                    let now = clock.lock().unwrap().now();
+                    let gestures = recognizer.lock().unwrap().tick(now);
+                    for args in gestures {
+                        event.lock().unwrap().trigger(args);
+                    }
+                }
+            })
+        };
+
+        GestureDetector { event, recognizer, input: Some(input), stop, thread: Some(thread) }
+    }
+
+    /// The buttons held down right now, in the order they were pressed.
+    pub fn pressed_buttons(&self) -> Vec<Button> {
+        self.recognizer.lock().unwrap().held.iter().map(|(_, _, button)| *button).collect()
+    }
+
+    pub fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    pub fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
+
+    /// Returns a receiver for every gesture from now on.
+    pub fn events(&self) -> mpsc::Receiver<TimedEvent> {
+        self.event.lock().unwrap().channel()
+    }
+}
+
+impl Drop for GestureDetector {
+    fn drop(&mut self) {
+        // stop following the launchpad, which may outlive the detector.
+        if let Some(input) = self.input.take() {
+            input.unsubscribe();
+        }
+
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Follows the device's stamps, so the timer thread can tell how late it is
+/// in the same units.
+struct Clock {
+    stamp: u64,
+    at: Instant
+}
+
+impl Clock {
+    fn new() -> Clock {
+        Clock { stamp: 0, at: Instant::now() }
+    }
+
+    fn sync(&mut self, stamp: u64) {
+        self.stamp = stamp;
+        self.at = Instant::now();
+    }
+
+    fn now(&self) -> u64 {
+        self.stamp + self.at.elapsed().as_micros() as u64
+    }
+}
+
+/// Feeds the launchpad's events into the recognizer.
+struct Input {
+    recognizer: Arc<Mutex<Recognizer>>,
+    clock: Arc<Mutex<Clock>>,
+    event: Arc<Mutex<LaunchpadEvent>>
+}
+
+impl LaunchpadEventHandler for Input {
+    fn notify(&self, args: &LaunchpadEventArgs) {
+        self.notify_at(args, EventTime::now());
+    }
+
+    fn notify_at(&self, args: &LaunchpadEventArgs, time: EventTime) {
+        // events not from a MIDI message are timed by the clock instead.
+        let stamp = match time.stamp {
+            Some(stamp) => {
+                self.clock.lock().unwrap().sync(stamp);
+                stamp
+            }
+            None => self.clock.lock().unwrap().now()
+        };
+
+        let gestures = self.recognizer.lock().unwrap().handle(args, stamp);
+        for args in gestures {
+            self.event.lock().unwrap().trigger_timed(args, time);
+        }
+    }
+}
+
+struct Press {
+    button: Button,
+    at: u64,
+    second: bool,
+    long_pressed: bool,
+    repeats: u32
+}
+
+#[derive(Default)]
+struct Pad {
+    press: Option<Press>,
+    /// When the last tap was released, if it may still become a double tap.
+    pending_tap: Option<(Button, u64)>
+}
+
+/// Pads pressed one after another in a line, which may become a swipe.
+struct Track {
+    direction: Option<SwipeDirection>,
+    from: (usize, usize, Button),
+    to: (usize, usize, Button),
+    length: usize,
+    last: u64
+}
+
+/// Recognises gestures from pad events, with all times in microseconds.
+struct Recognizer {
+    config: GestureConfig,
+    pads: HashMap<(usize, usize), Pad>,
+    held: Vec<(usize, usize, Button)>,
+    track: Option<Track>
+}
+
+impl Recognizer {
+    fn new(config: GestureConfig) -> Recognizer {
+        Recognizer { config, pads: HashMap::new(), held: Vec::new(), track: None }
+    }
+
+    /// Reports the track as a swipe if it is long enough.
+    fn finish(&self, track: Track) -> Option<LaunchpadEventArgs> {
+        match track.direction {
+            Some(direction) if track.length >= self.config.swipe_length => Some(LaunchpadEventArgs::Swipe {
+                direction,
+                from: track.from.2,
+                to: track.to.2,
+                length: track.length
+            }),
+            _ => None
+        }
+    }
+
+    /// Extends the current track with a press, or starts a new one.
+    fn follow(&mut self, x: usize, y: usize, button: Button, stamp: u64) -> Option<LaunchpadEventArgs> {
+        let swipe_interval = self.config.swipe_interval.as_micros() as u64;
+
+        if let Some(track) = self.track.as_mut() {
+            let direction = SwipeDirection::between((track.to.0, track.to.1), (x, y));
+            let in_time = stamp.saturating_sub(track.last) <= swipe_interval;
+
+            if in_time && direction.is_some() && (track.direction.is_none() || track.direction == direction) {
+                track.direction = direction;
+                track.to = (x, y, button);
+                track.length += 1;
+                track.last = stamp;
+                return None;
+            }
+        }
+
+        let start = Track { direction: None, from: (x, y, button), to: (x, y, button), length: 1, last: stamp };
+        self.track.replace(start).and_then(|track| self.finish(track))
+    }
+
+    fn handle(&mut self, args: &LaunchpadEventArgs, stamp: u64) -> Vec<LaunchpadEventArgs> {
+        let long_press = self.config.long_press.as_micros() as u64;
+        let double_tap = self.config.double_tap.as_micros() as u64;
+
+        let mut gestures = Vec::new();
+        match *args {
+            LaunchpadEventArgs::Pressed { x, y, button, .. } => {
+                let pad = self.pads.entry((x, y)).or_default();
+
+                let second = match pad.pending_tap.take() {
+                    Some((_, released)) if stamp.saturating_sub(released) <= double_tap => true,
+                    Some((button, _)) => {
+                        gestures.push(LaunchpadEventArgs::Tap { x, y, button });
+                        false
+                    }
+                    None => false
+                };
+
+                pad.press = Some(Press { button, at: stamp, second, long_pressed: false, repeats: 0 });
+
+                gestures.extend(self.follow(x, y, button, stamp));
+
+                if !self.held.is_empty() {
+                    let mut buttons: Vec<Button> = self.held.iter().map(|(_, _, button)| *button).collect();
+                    buttons.push(button);
+                    gestures.push(LaunchpadEventArgs::Chord { buttons });
+                }
+                self.held.push((x, y, button));
+            }
+            LaunchpadEventArgs::Released { x, y, button } => {
+                self.held.retain(|(hx, hy, _)| (*hx, *hy) != (x, y));
+
+                let pad = self.pads.entry((x, y)).or_default();
+
+                match pad.press.take() {
+                    Some(Press { long_pressed: true, .. }) => (),
+                    Some(press) if stamp.saturating_sub(press.at) >= long_press => {
+                        gestures.push(LaunchpadEventArgs::LongPress { x, y, button });
+                    }
+                    Some(Press { second: true, .. }) => {
+                        gestures.push(LaunchpadEventArgs::DoubleTap { x, y, button });
+                    }
+                    Some(_) if double_tap == 0 => {
+                        gestures.push(LaunchpadEventArgs::Tap { x, y, button });
+                    }
+                    Some(_) => pad.pending_tap = Some((button, stamp)),
+                    None => ()
+                }
+            }
+            LaunchpadEventArgs::Disconnected => {
+                self.pads.clear();
+                self.held.clear();
+                self.track = None;
+            }
+            _ => ()
+        }
+        gestures
+    }
+
+    fn tick(&mut self, now: u64) -> Vec<LaunchpadEventArgs> {
+        let long_press = self.config.long_press.as_micros() as u64;
+        let double_tap = self.config.double_tap.as_micros() as u64;
+        let repeat_delay = self.config.repeat_delay.as_micros() as u64;
+        let repeat_interval = self.config.repeat_interval.as_micros().max(1) as u64;
+
+        let swipe_interval = self.config.swipe_interval.as_micros() as u64;
+
+        let mut gestures = Vec::new();
+        if self.track.as_ref().is_some_and(|track| now.saturating_sub(track.last) > swipe_interval) {
+            let track = self.track.take().unwrap();
+            gestures.extend(self.finish(track));
+        }
+
+        for ((x, y), pad) in self.pads.iter_mut() {
+            let (x, y) = (*x, *y);
+
+            if let Some((button, released)) = pad.pending_tap {
+                if now.saturating_sub(released) > double_tap {
+                    pad.pending_tap = None;
+                    gestures.push(LaunchpadEventArgs::Tap { x, y, button });
+                }
+            }
+
+            if let Some(press) = pad.press.as_mut() {
+                let held = now.saturating_sub(press.at);
+
+                if !press.long_pressed && held >= long_press {
+                    press.long_pressed = true;
+                    gestures.push(LaunchpadEventArgs::LongPress { x, y, button: press.button });
+                }
+
+                if held >= repeat_delay {
+                    let count = ((held - repeat_delay) / repeat_interval) as u32 + 1;
+                    if count > press.repeats {
+                        press.repeats = count;
+                        // a held pad counts as long pressed once it repeats.
+                        press.long_pressed = true;
+                        gestures.push(LaunchpadEventArgs::HoldRepeat { x, y, button: press.button, count });
+                    }
+                }
+            }
+        }
+        gestures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Recorder};
+    use crate::launchpad_x::LaunchpadX;
+
+    const MS: u64 = 1000;
+
+    fn pressed(x: usize, y: usize) -> LaunchpadEventArgs {
+        LaunchpadEventArgs::Pressed { x, y, button: Button::Grid { x, y }, velocity: 127 }
+    }
+
+    fn released(x: usize, y: usize) -> LaunchpadEventArgs {
+        LaunchpadEventArgs::Released { x, y, button: Button::Grid { x, y } }
+    }
+
+    #[test]
+    fn recognizes_taps() {
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+        let button = Button::Grid { x: 1, y: 1 };
+
+        assert!(recognizer.handle(&pressed(1, 1), 0).is_empty());
+        assert!(recognizer.handle(&released(1, 1), 100 * MS).is_empty());
+        assert!(recognizer.tick(300 * MS).is_empty());
+        assert_eq!(recognizer.tick(401 * MS), vec![LaunchpadEventArgs::Tap { x: 1, y: 1, button }]);
+
+        recognizer.handle(&pressed(1, 1), 1000 * MS);
+        recognizer.handle(&released(1, 1), 1050 * MS);
+        recognizer.handle(&pressed(1, 1), 1200 * MS);
+        assert_eq!(recognizer.handle(&released(1, 1), 1250 * MS), vec![LaunchpadEventArgs::DoubleTap { x: 1, y: 1, button }]);
+        assert!(recognizer.tick(2000 * MS).is_empty());
+    }
+
+    #[test]
+    fn recognizes_long_press_and_repeat() {
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+        let button = Button::Grid { x: 2, y: 3 };
+
+        recognizer.handle(&pressed(2, 3), 0);
+        assert!(recognizer.tick(499 * MS).is_empty());
+        assert_eq!(recognizer.tick(500 * MS), vec![
+            LaunchpadEventArgs::LongPress { x: 2, y: 3, button },
+            LaunchpadEventArgs::HoldRepeat { x: 2, y: 3, button, count: 1 }
+        ]);
+        assert!(recognizer.tick(550 * MS).is_empty());
+        assert_eq!(recognizer.tick(610 * MS), vec![LaunchpadEventArgs::HoldRepeat { x: 2, y: 3, button, count: 2 }]);
+        assert!(recognizer.handle(&released(2, 3), 650 * MS).is_empty());
+
+        // a long press the timer has not seen yet is reported on release.
+        recognizer.handle(&pressed(2, 3), 1000 * MS);
+        assert_eq!(recognizer.handle(&released(2, 3), 1600 * MS), vec![LaunchpadEventArgs::LongPress { x: 2, y: 3, button }]);
+    }
+
+    #[test]
+    fn recognizes_chords_and_held_buttons() {
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+
+        recognizer.handle(&pressed(8, 1), 0);
+        assert_eq!(recognizer.handle(&pressed(3, 4), 1000 * MS), vec![
+            LaunchpadEventArgs::Chord { buttons: vec![Button::Grid { x: 8, y: 1 }, Button::Grid { x: 3, y: 4 }] }
+        ]);
+        assert_eq!(recognizer.held.len(), 2);
+
+        recognizer.handle(&released(8, 1), 1100 * MS);
+        assert_eq!(recognizer.held, vec![(3, 4, Button::Grid { x: 3, y: 4 })]);
+
+        recognizer.handle(&LaunchpadEventArgs::Disconnected, 1200 * MS);
+        assert!(recognizer.held.is_empty());
+    }
+
+    #[test]
+    fn recognizes_swipes() {
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+        let swipe = LaunchpadEventArgs::Swipe {
+            direction: SwipeDirection::Right,
+            from: Button::Grid { x: 2, y: 5 },
+            to: Button::Grid { x: 5, y: 5 },
+            length: 4
+        };
+
+        for (i, x) in (2..6).enumerate() {
+            recognizer.handle(&pressed(x, 5), i as u64 * 50 * MS);
+            recognizer.handle(&released(x, 5), i as u64 * 50 * MS + 40 * MS);
+        }
+        assert!(!recognizer.tick(250 * MS).contains(&swipe));
+        assert!(recognizer.tick(400 * MS).contains(&swipe));
+
+        // a turn, a gap or too few pads is not a swipe.
+        recognizer.handle(&pressed(1, 1), 1000 * MS);
+        recognizer.handle(&pressed(2, 1), 1050 * MS);
+        recognizer.handle(&pressed(2, 2), 1100 * MS);
+        recognizer.handle(&pressed(2, 3), 1500 * MS);
+        let gestures = recognizer.tick(2000 * MS);
+        assert!(!gestures.iter().any(|args| matches!(args, LaunchpadEventArgs::Swipe { .. })));
+    }
+
+    #[test]
+    fn times_gestures_with_midi_stamps() {
+        let (launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        let detector = GestureDetector::new(&launchpad, GestureConfig::default());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        detector.set_event_handler(Box::new(Recorder { events: events.clone() }));
+
+        transport.receive_at(0, &[0x90, 11, 127]);
+        transport.receive_at(800 * MS, &[0x90, 11, 0]);
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            LaunchpadEventArgs::LongPress { x: 0, y: 8, button: Button::Grid { x: 0, y: 7 } }
+        ]);
+    }
+
+    #[test]
+    fn unsubscribes_from_launchpad_when_dropped() {
+        let (launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        let detector = GestureDetector::new(&launchpad, GestureConfig::default());
+        let recognizer = detector.recognizer.clone();
+        drop(detector);
+
+        transport.receive_at(0, &[0x90, 11, 127]);
+        launchpad.get_event().lock().unwrap().subscribe_fn(|_, _| ()).detach();
+        assert_eq!(Arc::strong_count(&recognizer), 1);
+        assert!(recognizer.lock().unwrap().held.is_empty());
+    }
+}
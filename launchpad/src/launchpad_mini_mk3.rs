@@ -0,0 +1,140 @@
+use crate::{Launchpad, LaunchpadOutput, LaunchpadColor};
+use crate::color::{ColorProfile, LightMode};
+use crate::button::ButtonLayout;
+use crate::event::*;
+use crate::device::{self, DeviceInfo};
+use crate::error::LaunchpadError;
+use crate::launchpad_x::LaunchpadX;
+use crate::protocol;
+use crate::selector::PortSelector;
+use crate::transport::{MidiTransport, MidirTransport};
+
+use std::sync::{Arc, Mutex};
+
+/// The Launchpad Mini MK3, which shares the Launchpad X's note layout and
+/// lighting messages under its own SysEx device byte.
+pub struct LaunchpadMiniMk3 {
+    transport: Box<dyn MidiTransport>,
+
+    event: Arc<Mutex<LaunchpadEvent>>,
+    info: Arc<Mutex<Option<DeviceInfo>>>,
+
+    profile: Option<ColorProfile>
+}
+
+impl LaunchpadMiniMk3 {
+    /// SysEx device byte identifying the Launchpad Mini MK3.
+    pub const DEVICE_ID: u8 = 0x0D;
+
+    /// Matches the names of the port the Launchpad Mini MK3 accepts Programmer mode messages on.
+    pub const PORT_PATTERN: &'static str = r"Launchpad Mini MK3 MIDI 2|LPMiniMK3 MIDI";
+
+    pub fn init(selector: &PortSelector) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        let transport = MidirTransport::connect(selector)?;
+
+        Ok(Box::new(LaunchpadMiniMk3::with_transport(Box::new(transport))?))
+    }
+
+    pub fn with_transport(transport: Box<dyn MidiTransport>) -> Result<LaunchpadMiniMk3, LaunchpadError> {
+        LaunchpadMiniMk3::with_transport_and_info(transport, None)
+    }
+
+    /// Opens a Launchpad Mini MK3 over `transport` in Programmer mode. The
+    /// Device Inquiry is only sent if `known` does not already hold its reply.
+    pub fn with_transport_and_info(mut transport: Box<dyn MidiTransport>, known: Option<DeviceInfo>) -> Result<LaunchpadMiniMk3, LaunchpadError> {
+        let (event, info) = device::listen(&mut *transport, known, LaunchpadMiniMk3::parse_midi_message);
+
+        let mut launchpad = LaunchpadMiniMk3 {
+            transport, event, info, profile: None
+        };
+
+        launchpad.send(&protocol::programmer_mode(LaunchpadMiniMk3::DEVICE_ID, true))?;
+        device::verify(&mut *launchpad.transport, known)?;
+
+        Ok(launchpad)
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+        self.transport.send(message)
+    }
+
+    pub fn parse_midi_message(message: &[u8]) -> Option<LaunchpadEventArgs> {
+        protocol::parse_message(&LaunchpadX::LED_LAYOUT, ButtonLayout::Standard, message)
+    }
+}
+
+impl LaunchpadOutput for LaunchpadMiniMk3 {
+    fn grid_size(&self) -> (usize, usize) {
+        (9, 9)
+    }
+
+    fn has_light_modes(&self) -> bool {
+        true
+    }
+
+    fn set_color_profile(&mut self, profile: Option<ColorProfile>) {
+        self.profile = profile;
+    }
+
+    fn set_brightness(&mut self, level: u8) -> Result<(), LaunchpadError> {
+        protocol::set_brightness(&mut *self.transport, LaunchpadMiniMk3::DEVICE_ID, level)
+    }
+
+    fn brightness(&mut self) -> Result<u8, LaunchpadError> {
+        protocol::brightness(&mut *self.transport, &self.event, LaunchpadMiniMk3::DEVICE_ID)
+    }
+
+    fn set_sleeping(&mut self, sleeping: bool) -> Result<(), LaunchpadError> {
+        protocol::set_sleeping(&mut *self.transport, LaunchpadMiniMk3::DEVICE_ID, sleeping)
+    }
+
+    fn is_sleeping(&mut self) -> Result<bool, LaunchpadError> {
+        protocol::is_sleeping(&mut *self.transport, &self.event, LaunchpadMiniMk3::DEVICE_ID)
+    }
+
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        let notes = protocol::layout_lights(&LaunchpadX::LED_LAYOUT, lights)?;
+
+        self.send(&protocol::rgb_lights(LaunchpadMiniMk3::DEVICE_ID, self.profile.as_ref(), &notes))
+    }
+
+    fn set_lights_with_mode(&mut self, lights: &[(usize, usize, LaunchpadColor, LightMode)]) -> Result<(), LaunchpadError> {
+        let notes = protocol::layout_mode_lights(&LaunchpadX::LED_LAYOUT, lights)?;
+
+        self.send(&protocol::mode_lights(LaunchpadMiniMk3::DEVICE_ID, self.profile.as_ref(), &notes))
+    }
+}
+
+impl Launchpad for LaunchpadMiniMk3 {
+    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
+
+    fn device_info(&self) -> Option<DeviceInfo> {
+        *self.info.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::LoopbackTransport;
+
+    #[test]
+    fn uses_mini_mk3_device_byte() {
+        let transport = LoopbackTransport::new();
+        let mut launchpad = LaunchpadMiniMk3::with_transport(Box::new(transport.clone())).unwrap();
+
+        launchpad.set_light(0, 8, LaunchpadColor::GREEN).unwrap();
+
+        assert_eq!(transport.take_sent(), vec![
+            vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0D, 0x0E, 0x01, 0xF7],
+            device::DEVICE_INQUIRY.to_vec(),
+            vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0D, 0x03, 0x03, 11, 0, 127, 0, 0xF7]
+        ]);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{Launchpad, LaunchpadOutput, LaunchpadColor};
+use crate::color::ColorProfile;
+use crate::button::ButtonLayout;
+use crate::event::*;
+use crate::device::{self, DeviceInfo};
+use crate::error::LaunchpadError;
+use crate::protocol::{self, NO_PAD};
+use crate::selector::PortSelector;
+use crate::transport::{MidiTransport, MidirTransport};
+
+use std::sync::{Arc, Mutex};
+
+/// The original RGB Launchpad MK2.
+///
+/// It has no Programmer mode; the top row sends CC 104-111 and the grid and
+/// side column send the same notes as the Launchpad X. Colours are sent with
+/// 6-bit channels, so each channel loses its lowest bit.
+pub struct LaunchpadMk2 {
+    transport: Box<dyn MidiTransport>,
+
+    event: Arc<Mutex<LaunchpadEvent>>,
+    info: Arc<Mutex<Option<DeviceInfo>>>,
+
+    profile: Option<ColorProfile>
+}
+
+impl LaunchpadMk2 {
+    /// SysEx device byte identifying the Launchpad MK2.
+    pub const DEVICE_ID: u8 = 0x18;
+
+    pub const PORT_PATTERN: &'static str = r"Launchpad MK2";
+
+    pub(crate) const LED_LAYOUT: [[u8;9];9] = [
+        [ 104, 105, 106, 107, 108, 109, 110, 111, NO_PAD ],
+        [ 81, 82, 83, 84, 85, 86, 87, 88, 89 ],
+        [ 71, 72, 73, 74, 75, 76, 77, 78, 79 ],
+        [ 61, 62, 63, 64, 65, 66, 67, 68, 69 ],
+        [ 51, 52, 53, 54, 55, 56, 57, 58, 59 ],
+        [ 41, 42, 43, 44, 45, 46, 47, 48, 49 ],
+        [ 31, 32, 33, 34, 35, 36, 37, 38, 39 ],
+        [ 21, 22, 23, 24, 25, 26, 27, 28, 29 ],
+        [ 11, 12, 13, 14, 15, 16, 17, 18, 19 ],
+    ];
+
+    pub fn init(selector: &PortSelector) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        let transport = MidirTransport::connect(selector)?;
+
+        Ok(Box::new(LaunchpadMk2::with_transport(Box::new(transport))?))
+    }
+
+    pub fn with_transport(transport: Box<dyn MidiTransport>) -> Result<LaunchpadMk2, LaunchpadError> {
+        LaunchpadMk2::with_transport_and_info(transport, None)
+    }
+
+    /// Opens a Launchpad MK2 over `transport` in its Session layout. `known`
+    /// is its earlier reply to the Device Inquiry, if it has given one.
+    pub fn with_transport_and_info(mut transport: Box<dyn MidiTransport>, known: Option<DeviceInfo>) -> Result<LaunchpadMk2, LaunchpadError> {
+        let (event, info) = device::listen(&mut *transport, known, LaunchpadMk2::parse_midi_message);
+
+        let mut launchpad = LaunchpadMk2 {
+            transport, event, info, profile: None
+        };
+
+        // select the Session layout, which uses the note numbers above.
+        launchpad.send(&protocol::sysex(LaunchpadMk2::DEVICE_ID, &[0x22, 0x00]))?;
+        device::verify(&mut *launchpad.transport, known)?;
+
+        Ok(launchpad)
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+        self.transport.send(message)
+    }
+
+    pub fn parse_midi_message(message: &[u8]) -> Option<LaunchpadEventArgs> {
+        protocol::parse_message(&LaunchpadMk2::LED_LAYOUT, ButtonLayout::Classic, message)
+    }
+}
+
+impl LaunchpadOutput for LaunchpadMk2 {
+    fn grid_size(&self) -> (usize, usize) {
+        (9, 9)
+    }
+
+    fn button_layout(&self) -> ButtonLayout {
+        ButtonLayout::Classic
+    }
+
+    fn set_color_profile(&mut self, profile: Option<ColorProfile>) {
+        self.profile = profile;
+    }
+
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        let notes = protocol::layout_lights(&LaunchpadMk2::LED_LAYOUT, lights)?;
+
+        let mut payload = vec![0x0B]; // set LED RGB command.
+        for (note, color) in notes.iter() {
+            let color = protocol::calibrate(self.profile.as_ref(), *color);
+            payload.push(*note);
+            payload.extend_from_slice(&[color.red >> 1, color.green >> 1, color.blue >> 1]);
+        }
+
+        self.send(&protocol::sysex(LaunchpadMk2::DEVICE_ID, &payload))
+    }
+
+    fn has_light(&self, x: usize, y: usize) -> bool {
+        protocol::layout_note(&LaunchpadMk2::LED_LAYOUT, x, y).is_ok()
+    }
+}
+
+impl Launchpad for LaunchpadMk2 {
+    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
+
+    fn device_info(&self) -> Option<DeviceInfo> {
+        *self.info.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::button::Button;
+    use crate::color::LightMode;
+    use crate::LaunchpadState;
+
+    #[test]
+    fn sends_six_bit_rgb() {
+        let (mut launchpad, transport) = testing::open(LaunchpadMk2::with_transport);
+
+        launchpad.set_box(6, 0, 2, 1, LaunchpadColor { red: 127, green: 64, blue: 1 }).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![
+            0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0B,
+            110, 63, 32, 0,
+            111, 63, 32, 0,
+            0xF7
+        ]]);
+
+        assert!(launchpad.set_light(8, 0, LaunchpadColor::RED).is_err());
+        assert_eq!(LaunchpadMk2::parse_midi_message(&[0xB0, 104, 127]), Some(LaunchpadEventArgs::Pressed { x: 0, y: 0, button: Button::Up, velocity: 127 }));
+    }
+
+    #[test]
+    fn applies_color_profile_before_halving() {
+        let (mut launchpad, transport) = testing::open(LaunchpadMk2::with_transport);
+
+        launchpad.set_color_profile(Some(ColorProfile::with_gamma(2.0)));
+        launchpad.set_light(0, 1, LaunchpadColor { red: 64, green: 127, blue: 0 }).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0B, 81, 16, 63, 0, 0xF7]]);
+    }
+
+    #[test]
+    fn shows_palette_modes_as_static_colors() {
+        let (mut launchpad, transport) = testing::open(LaunchpadMk2::with_transport);
+
+        launchpad.set_lights_with_mode(&[
+            (0, 1, LaunchpadColor::BLACK, LightMode::Pulse(5)),
+            (1, 1, LaunchpadColor::BLACK, LightMode::Flash { a: 21, b: 0 })
+        ]).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x18, 0x0B, 81, 63, 0, 0, 82, 0, 63, 0, 0xF7]]);
+
+        assert!(matches!(
+            launchpad.set_light_with_mode(0, 1, LaunchpadColor::BLACK, LightMode::Palette(200)),
+            Err(LaunchpadError::InvalidPaletteIndex(200))
+        ));
+        assert!(transport.take_sent().is_empty());
+    }
+
+    #[test]
+    fn skips_missing_corner_in_full_state() {
+        let (mut launchpad, transport) = testing::open(LaunchpadMk2::with_transport);
+
+        launchpad.set_state(LaunchpadState::new()).unwrap();
+        assert_eq!(transport.take_sent()[0].len(), 7 + 80 * 4 + 1);
+
+        launchpad.set_box(0, 0, 9, 1, LaunchpadColor::RED).unwrap();
+        assert_eq!(transport.take_sent()[0].len(), 7 + 8 * 4 + 1);
+    }
+}
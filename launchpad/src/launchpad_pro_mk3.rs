@@ -0,0 +1,179 @@
+use crate::{Launchpad, LaunchpadOutput, LaunchpadColor};
+use crate::color::{ColorProfile, LightMode};
+use crate::button::ButtonLayout;
+use crate::event::*;
+use crate::device::{self, DeviceInfo};
+use crate::error::LaunchpadError;
+use crate::protocol::{self, NO_PAD};
+use crate::selector::PortSelector;
+use crate::transport::{MidiTransport, MidirTransport};
+
+use std::sync::{Arc, Mutex};
+
+/// The Launchpad Pro MK3, whose buttons form a 10x10 ring around the 8x8 grid.
+///
+/// `x == 0` is the left column, `x == 9` the right column, `y == 0` the top
+/// row and `y == 9` the row of buttons below the grid. The bottom corners
+/// have no button.
+pub struct LaunchpadProMk3 {
+    transport: Box<dyn MidiTransport>,
+
+    event: Arc<Mutex<LaunchpadEvent>>,
+    info: Arc<Mutex<Option<DeviceInfo>>>,
+
+    profile: Option<ColorProfile>
+}
+
+impl LaunchpadProMk3 {
+    /// SysEx device byte identifying the Launchpad Pro MK3.
+    pub const DEVICE_ID: u8 = 0x0E;
+
+    /// Matches the names of the port the Launchpad Pro MK3 accepts Programmer mode messages on.
+    pub const PORT_PATTERN: &'static str = r"LPProMK3 MIDI";
+
+    pub(crate) const LED_LAYOUT: [[u8;10];10] = [
+        [ 90, 91, 92, 93, 94, 95, 96, 97, 98, 99 ],
+        [ 80, 81, 82, 83, 84, 85, 86, 87, 88, 89 ],
+        [ 70, 71, 72, 73, 74, 75, 76, 77, 78, 79 ],
+        [ 60, 61, 62, 63, 64, 65, 66, 67, 68, 69 ],
+        [ 50, 51, 52, 53, 54, 55, 56, 57, 58, 59 ],
+        [ 40, 41, 42, 43, 44, 45, 46, 47, 48, 49 ],
+        [ 30, 31, 32, 33, 34, 35, 36, 37, 38, 39 ],
+        [ 20, 21, 22, 23, 24, 25, 26, 27, 28, 29 ],
+        [ 10, 11, 12, 13, 14, 15, 16, 17, 18, 19 ],
+        [ NO_PAD, 101, 102, 103, 104, 105, 106, 107, 108, NO_PAD ],
+    ];
+
+    pub fn init(selector: &PortSelector) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        let transport = MidirTransport::connect(selector)?;
+
+        Ok(Box::new(LaunchpadProMk3::with_transport(Box::new(transport))?))
+    }
+
+    pub fn with_transport(transport: Box<dyn MidiTransport>) -> Result<LaunchpadProMk3, LaunchpadError> {
+        LaunchpadProMk3::with_transport_and_info(transport, None)
+    }
+
+    /// Opens a Launchpad Pro MK3 over `transport` in Programmer mode, asking
+    /// for its model and firmware unless `known` already has them.
+    pub fn with_transport_and_info(mut transport: Box<dyn MidiTransport>, known: Option<DeviceInfo>) -> Result<LaunchpadProMk3, LaunchpadError> {
+        let (event, info) = device::listen(&mut *transport, known, LaunchpadProMk3::parse_midi_message);
+
+        let mut launchpad = LaunchpadProMk3 {
+            transport, event, info, profile: None
+        };
+
+        launchpad.send(&protocol::programmer_mode(LaunchpadProMk3::DEVICE_ID, true))?;
+        device::verify(&mut *launchpad.transport, known)?;
+
+        Ok(launchpad)
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+        self.transport.send(message)
+    }
+
+    pub fn parse_midi_message(message: &[u8]) -> Option<LaunchpadEventArgs> {
+        protocol::parse_message(&LaunchpadProMk3::LED_LAYOUT, ButtonLayout::Pro, message)
+    }
+}
+
+impl LaunchpadOutput for LaunchpadProMk3 {
+    fn grid_size(&self) -> (usize, usize) {
+        (10, 10)
+    }
+
+    fn has_light_modes(&self) -> bool {
+        true
+    }
+
+    fn button_layout(&self) -> ButtonLayout {
+        ButtonLayout::Pro
+    }
+
+    fn set_color_profile(&mut self, profile: Option<ColorProfile>) {
+        self.profile = profile;
+    }
+
+    fn set_brightness(&mut self, level: u8) -> Result<(), LaunchpadError> {
+        protocol::set_brightness(&mut *self.transport, LaunchpadProMk3::DEVICE_ID, level)
+    }
+
+    fn brightness(&mut self) -> Result<u8, LaunchpadError> {
+        protocol::brightness(&mut *self.transport, &self.event, LaunchpadProMk3::DEVICE_ID)
+    }
+
+    fn set_sleeping(&mut self, sleeping: bool) -> Result<(), LaunchpadError> {
+        protocol::set_sleeping(&mut *self.transport, LaunchpadProMk3::DEVICE_ID, sleeping)
+    }
+
+    fn is_sleeping(&mut self) -> Result<bool, LaunchpadError> {
+        protocol::is_sleeping(&mut *self.transport, &self.event, LaunchpadProMk3::DEVICE_ID)
+    }
+
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        let notes = protocol::layout_lights(&LaunchpadProMk3::LED_LAYOUT, lights)?;
+
+        self.send(&protocol::rgb_lights(LaunchpadProMk3::DEVICE_ID, self.profile.as_ref(), &notes))
+    }
+
+    fn set_lights_with_mode(&mut self, lights: &[(usize, usize, LaunchpadColor, LightMode)]) -> Result<(), LaunchpadError> {
+        let notes = protocol::layout_mode_lights(&LaunchpadProMk3::LED_LAYOUT, lights)?;
+
+        self.send(&protocol::mode_lights(LaunchpadProMk3::DEVICE_ID, self.profile.as_ref(), &notes))
+    }
+
+    fn has_light(&self, x: usize, y: usize) -> bool {
+        protocol::layout_note(&LaunchpadProMk3::LED_LAYOUT, x, y).is_ok()
+    }
+}
+
+impl Launchpad for LaunchpadProMk3 {
+    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
+
+    fn device_info(&self) -> Option<DeviceInfo> {
+        *self.info.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::button::Button;
+    use crate::state::LaunchpadState;
+
+    #[test]
+    fn parses_side_rows() {
+        assert_eq!(LaunchpadProMk3::parse_midi_message(&[0xB0, 50, 127]), Some(LaunchpadEventArgs::Pressed { x: 0, y: 4, button: Button::Other { x: 0, y: 4 }, velocity: 127 }));
+        assert_eq!(LaunchpadProMk3::parse_midi_message(&[0xB0, 103, 0]), Some(LaunchpadEventArgs::Released { x: 3, y: 9, button: Button::Other { x: 3, y: 9 } }));
+        assert_eq!(LaunchpadProMk3::parse_midi_message(&[0x90, 11, 20]), Some(LaunchpadEventArgs::Pressed { x: 1, y: 8, button: Button::Grid { x: 0, y: 7 }, velocity: 20 }));
+    }
+
+    #[test]
+    fn lights_full_ring() {
+        let (mut launchpad, transport) = testing::open(LaunchpadProMk3::with_transport);
+
+        launchpad.set_light(0, 9, LaunchpadColor::RED).unwrap_err();
+        launchpad.set_light(1, 9, LaunchpadColor::RED).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0E, 0x03, 0x03, 101, 127, 0, 0, 0xF7]]);
+
+        launchpad.clear_grid().unwrap();
+        assert_eq!(transport.take_sent()[0].len(), 7 + 98 * 5 + 1);
+
+        // the bottom corners have no light, so a full state skips them.
+        let mut state = LaunchpadState::with_size(10, 10);
+        state.set_light(9, 8, LaunchpadColor::BLUE).unwrap();
+        launchpad.set_state(state).unwrap();
+        let sent = transport.take_sent();
+        assert_eq!(sent[0].len(), 7 + 98 * 5 + 1);
+        assert_eq!(&sent[0][7 + 89 * 5..7 + 90 * 5], &[0x03, 19, 0, 0, 127]);
+        assert_eq!(launchpad.pad(0, 9), None);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{Launchpad, LaunchpadOutput, LaunchpadColor};
+use crate::color::ColorProfile;
+use crate::button::ButtonLayout;
+use crate::event::*;
+use crate::device::{self, DeviceInfo};
+use crate::error::LaunchpadError;
+use crate::protocol;
+use crate::selector::PortSelector;
+use crate::transport::{MidiTransport, MidirTransport};
+
+use std::sync::{Arc, Mutex};
+
+/// The Launchpad S and Launchpad Mini MK2, which only have red and green LEDs
+/// with four brightness levels each.
+///
+/// Colours are shown as the nearest red/green brightness, so blue is dropped.
+/// The top row is addressed with CC 104-111 and everything else with notes in
+/// the X-Y layout, where each row of the grid starts 16 notes after the last.
+pub struct LaunchpadS {
+    transport: Box<dyn MidiTransport>,
+
+    event: Arc<Mutex<LaunchpadEvent>>,
+    info: Arc<Mutex<Option<DeviceInfo>>>,
+
+    profile: Option<ColorProfile>
+}
+
+impl LaunchpadS {
+    pub const PORT_PATTERN: &'static str = r"Launchpad (S|Mini)( MIDI|$)";
+
+    /// Returns the status byte and note or controller number for the light at `(x, y)`.
+    fn lookup_message(x: usize, y: usize) -> Result<(u8, u8), LaunchpadError> {
+        match (x, y) {
+            (0..=7, 0) => Ok((0xB0, 104 + x as u8)),
+            (0..=8, 1..=8) => Ok((0x90, 16 * (y as u8 - 1) + x as u8)),
+            _ => Err(LaunchpadError::InvalidCoordinate { x, y })
+        }
+    }
+
+    /// Converts a colour to the velocity that shows its nearest red/green brightness.
+    pub fn color_velocity(color: LaunchpadColor) -> u8 {
+        let red = (color.red as u16 * 3 + 63) / 127;
+        let green = (color.green as u16 * 3 + 63) / 127;
+
+        // green in bits 4-5, red in bits 0-1, and 0x0C to clear and copy both buffers.
+        (green as u8) << 4 | red as u8 | 0x0C
+    }
+
+    pub fn init(selector: &PortSelector) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        let transport = MidirTransport::connect(selector)?;
+
+        Ok(Box::new(LaunchpadS::with_transport(Box::new(transport))?))
+    }
+
+    pub fn with_transport(transport: Box<dyn MidiTransport>) -> Result<LaunchpadS, LaunchpadError> {
+        LaunchpadS::with_transport_and_info(transport, None)
+    }
+
+    /// Opens a Launchpad S or Mini over `transport`, resetting it, which turns
+    /// every LED off. The Device Inquiry is skipped if `known` is given.
+    pub fn with_transport_and_info(mut transport: Box<dyn MidiTransport>, known: Option<DeviceInfo>) -> Result<LaunchpadS, LaunchpadError> {
+        let (event, info) = device::listen(&mut *transport, known, LaunchpadS::parse_midi_message);
+
+        let mut launchpad = LaunchpadS {
+            transport, event, info, profile: None
+        };
+
+        launchpad.send(&[0xB0, 0x00, 0x00])?; // reset, turning every LED off.
+        launchpad.send(&[0xB0, 0x00, 0x01])?; // select the X-Y layout.
+        device::verify(&mut *launchpad.transport, known)?;
+
+        Ok(launchpad)
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+        self.transport.send(message)
+    }
+
+    pub fn parse_midi_message(message: &[u8]) -> Option<LaunchpadEventArgs> {
+        if message.len() < 3 {
+            return None;
+        }
+
+        let (x, y) = match (message[0], message[1]) {
+            (0xB0, 104..=111) => (message[1] as usize - 104, 0),
+            (0x80, note) | (0x90, note) if note < 128 && note % 16 <= 8 => {
+                (note as usize % 16, note as usize / 16 + 1)
+            }
+            _ => return None
+        };
+
+        let button = ButtonLayout::Classic.button_at(x, y);
+
+        // the pads are not velocity sensitive, so presses are always 127.
+        match (message[0], message[2]) {
+            (0x80, _) | (_, 0) => Some(LaunchpadEventArgs::Released { x, y, button }),
+            _ => Some(LaunchpadEventArgs::Pressed { x, y, button, velocity: 127 })
+        }
+    }
+}
+
+impl LaunchpadOutput for LaunchpadS {
+    fn grid_size(&self) -> (usize, usize) {
+        (9, 9)
+    }
+
+    fn button_layout(&self) -> ButtonLayout {
+        ButtonLayout::Classic
+    }
+
+    fn set_color_profile(&mut self, profile: Option<ColorProfile>) {
+        self.profile = profile;
+    }
+
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        let mut messages = Vec::with_capacity(lights.len());
+        for (x, y, color) in lights.iter() {
+            let (status, number) = LaunchpadS::lookup_message(*x, *y)?;
+            let color = protocol::calibrate(self.profile.as_ref(), *color);
+            messages.push([status, number, LaunchpadS::color_velocity(color)]);
+        }
+
+        for message in messages.iter() {
+            self.send(message)?;
+        }
+        Ok(())
+    }
+
+    fn has_light(&self, x: usize, y: usize) -> bool {
+        LaunchpadS::lookup_message(x, y).is_ok()
+    }
+}
+
+impl Launchpad for LaunchpadS {
+    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
+
+    fn device_info(&self) -> Option<DeviceInfo> {
+        *self.info.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::button::Button;
+    use crate::LaunchpadState;
+
+    #[test]
+    fn maps_colors_to_red_green_velocity() {
+        assert_eq!(LaunchpadS::color_velocity(LaunchpadColor::BLACK), 0x0C);
+        assert_eq!(LaunchpadS::color_velocity(LaunchpadColor::RED), 0x0F);
+        assert_eq!(LaunchpadS::color_velocity(LaunchpadColor::GREEN), 0x3C);
+        assert_eq!(LaunchpadS::color_velocity(LaunchpadColor::BLUE), 0x0C);
+        assert_eq!(LaunchpadS::color_velocity(LaunchpadColor { red: 127, green: 40, blue: 0 }), 0x1F);
+    }
+
+    #[test]
+    fn addresses_top_row_and_grid() {
+        let (mut launchpad, transport) = testing::open(LaunchpadS::with_transport);
+
+        launchpad.set_light(2, 0, LaunchpadColor::RED).unwrap();
+        launchpad.set_light(8, 7, LaunchpadColor::GREEN).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![0xB0, 106, 0x0F], vec![0x90, 104, 0x3C]]);
+
+        assert_eq!(LaunchpadS::parse_midi_message(&[0xB0, 104, 127]), Some(LaunchpadEventArgs::Pressed { x: 0, y: 0, button: Button::Up, velocity: 127 }));
+        assert_eq!(LaunchpadS::parse_midi_message(&[0xB0, 111, 127]), Some(LaunchpadEventArgs::Pressed { x: 7, y: 0, button: Button::Mixer, velocity: 127 }));
+        assert_eq!(LaunchpadS::parse_midi_message(&[0x90, 104, 127]), Some(LaunchpadEventArgs::Pressed { x: 8, y: 7, button: Button::Scene(6), velocity: 127 }));
+        assert_eq!(LaunchpadS::parse_midi_message(&[0x90, 0, 0]), Some(LaunchpadEventArgs::Released { x: 0, y: 1, button: Button::Grid { x: 0, y: 0 } }));
+        assert_eq!(LaunchpadS::parse_midi_message(&[0x90, 9, 127]), None);
+    }
+
+    #[test]
+    fn reports_full_velocity_for_presses() {
+        assert_eq!(LaunchpadS::parse_midi_message(&[0x90, 0, 64]), Some(LaunchpadEventArgs::Pressed { x: 0, y: 1, button: Button::Grid { x: 0, y: 0 }, velocity: 127 }));
+    }
+
+    #[test]
+    fn applies_color_profile_before_velocity() {
+        let (mut launchpad, transport) = testing::open(LaunchpadS::with_transport);
+
+        let color = LaunchpadColor { red: 64, green: 0, blue: 0 };
+        launchpad.set_light(0, 1, color).unwrap();
+        launchpad.set_color_profile(Some(ColorProfile::with_gamma(2.0)));
+        launchpad.set_light(0, 1, color).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![0x90, 0, 0x0E], vec![0x90, 0, 0x0D]]);
+    }
+
+    #[test]
+    fn skips_missing_corner_in_full_state() {
+        let (mut launchpad, transport) = testing::open(LaunchpadS::with_transport);
+
+        launchpad.set_state(LaunchpadState::new()).unwrap();
+        assert_eq!(transport.take_sent().len(), 80);
+
+        launchpad.set_box(0, 0, 9, 1, LaunchpadColor::RED).unwrap();
+        assert_eq!(transport.take_sent().len(), 8);
+    }
+}
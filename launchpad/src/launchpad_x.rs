@@ -1,20 +1,50 @@
-use crate::{Launchpad, LaunchpadOutput, LaunchpadColor, LaunchpadState};
+use crate::{Launchpad, LaunchpadOutput, LaunchpadColor};
+use crate::color::{ColorProfile, LightMode};
+use crate::button::ButtonLayout;
 use crate::event::*;
-use std::io::{stdin, stdout, Write};
+use crate::device::{self, DeviceInfo};
+use crate::error::LaunchpadError;
+use crate::protocol;
+use crate::selector::PortSelector;
+use crate::transport::{MidiTransport, MidirTransport};
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// The layout and mode a Launchpad X was in before it was opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedMode {
+    pub layout: u8,
+    pub programmer: bool
+}
 
-use std::sync::{Arc, Mutex};
+/// A Launchpad X in Programmer mode.
+///
+/// When it is closed or dropped the grid is cleared and the device goes back
+/// to Live mode, or to the mode saved when it was opened.
+pub struct LaunchpadX {
+    transport: Box<dyn MidiTransport>,
 
-use regex::Regex;
+    event: Arc<Mutex<LaunchpadEvent>>,
+    info: Arc<Mutex<Option<DeviceInfo>>>,
 
-pub struct LaunchpadX {
-    input: midir::MidiInputConnection<Arc<Mutex<LaunchpadEvent>>>,
-    output: midir::MidiOutputConnection,
+    saved: Option<SavedMode>,
+    closed: bool,
 
-    event: Arc<Mutex<LaunchpadEvent>>
+    profile: Option<ColorProfile>
 }
 
 impl LaunchpadX {
-    const LED_LAYOUT: [[u8;9];9] = [
+    /// SysEx device byte identifying the Launchpad X.
+    pub const DEVICE_ID: u8 = 0x0C;
+
+    /// Matches the names of the port the Launchpad X accepts Programmer mode messages on.
+    pub const PORT_PATTERN: &'static str = r"Launchpad X MIDI 2|LPX MIDI";
+
+    /// How long to wait for the device to report its layout and mode.
+    pub const SAVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub(crate) const LED_LAYOUT: [[u8;9];9] = [
         [ 91, 92, 93, 94, 95, 96, 97, 98, 99 ],
         [ 81, 82, 83, 84, 85, 86, 87, 88, 89 ],
         [ 71, 72, 73, 74, 75, 76, 77, 78, 79 ],
@@ -26,270 +56,481 @@ impl LaunchpadX {
         [ 11, 12, 13, 14, 15, 16, 17, 18, 19 ],
     ];
 
-    fn lookup_layout(pad: u8) -> Option<(usize, usize)>
-    {
-        for y in 0..9 {
-            for x in 0..9 {
-                if LaunchpadX::LED_LAYOUT[y][x] == pad {
-                    return Some((x, y));
-                }
-            }
-        }
-        None
-    }
+    pub fn init(selector: &PortSelector) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        let transport = MidirTransport::connect(selector)?;
 
-    pub fn init() -> Result<Box<dyn Launchpad>, Box<dyn std::error::Error>> {
-        let event = Arc::new(Mutex::new(LaunchpadEvent::default()));
+        Ok(Box::new(LaunchpadX::with_transport(Box::new(transport))?))
+    }
 
-        let input = LaunchpadX::init_input_device(event.clone())?;
-        let output = LaunchpadX::init_output_device()?;
+    /// Like `init`, but first saves the layout and mode the device is in, so
+    /// that closing it puts them back.
+    pub fn init_saving_mode(selector: &PortSelector) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        let transport = MidirTransport::connect(selector)?;
 
-        let mut launchpad = Box::new(LaunchpadX {
-            input, output, event
-        });
+        Ok(Box::new(LaunchpadX::with_transport_saving_mode(Box::new(transport), LaunchpadX::SAVE_TIMEOUT)?))
+    }
 
-        let message = [0xF0,0x00,0x20,0x29,0x02,0x0C,0x0E,0x01,0xF7];
-        launchpad.send(&message);
+    pub fn with_transport(transport: Box<dyn MidiTransport>) -> Result<LaunchpadX, LaunchpadError> {
+        LaunchpadX::open(transport, None, None)
+    }
 
-        Ok(launchpad)
+    /// Opens a Launchpad X that `list_launchpads` or `create_launchpad` has
+    /// already identified as `known`, without sending it another inquiry.
+    pub fn with_transport_and_info(transport: Box<dyn MidiTransport>, known: Option<DeviceInfo>) -> Result<LaunchpadX, LaunchpadError> {
+        LaunchpadX::open(transport, None, known)
     }
 
-    pub fn get_input_device<'a>(
-        midi_in: &midir::MidiInput,
-        in_ports: &'a midir::MidiInputPorts
-    ) -> Result<&'a midir::MidiInputPort, Box<dyn std::error::Error>> {
-        match in_ports.len() {
-            0 => return Err("No input port found.".into()),
-            1 => {
-                println!("Only available port: {}", midi_in.port_name(&in_ports[0]).unwrap());
-                Ok(&in_ports[0])},
-            _ => {
-                // if it matches the RegEx, then use that port.
-                let re = Regex::new(r"Launchpad X MIDI 2").unwrap();
-                let mut selected_port = None;
-                for port in in_ports.iter() {
-                    if re.is_match(&midi_in.port_name(port).unwrap()) {
-                        selected_port = Some(port);
-                    }
-                }
-
-                match selected_port {
-                    None => {
-                        // otherwise, prompt the user for the port.
-                        println!("Available input ports:");
-                        for (i, p) in in_ports.iter().enumerate() {
-                            println!("{}: {}", i, midi_in.port_name(p).unwrap());
-                        }
-                        print!("Please select input port: ");
-                        stdout().flush()?;
-                        let mut input = String::new();
-                        stdin().read_line(&mut input)?;
-                        in_ports.get(input.trim().parse::<usize>()?)
-                            .ok_or("Invalid input port selected.".into())
-                    },
-                    Some(port) => {
-                        Ok(port)
-                    }
-                }
-            }
-        }
+    pub fn with_transport_saving_mode(mut transport: Box<dyn MidiTransport>, timeout: Duration) -> Result<LaunchpadX, LaunchpadError> {
+        let saved = LaunchpadX::read_mode(&mut *transport, timeout)?;
+
+        LaunchpadX::open(transport, Some(saved), None)
     }
 
-    pub fn init_input_device(
-        event: Arc<Mutex<LaunchpadEvent>>
-    ) -> Result<midir::MidiInputConnection<Arc<Mutex<LaunchpadEvent>>>, Box<dyn std::error::Error>> {
-        let midi_in = midir::MidiInput::new("Launchpad Output")?;
-        
-        let in_ports = midi_in.ports();
+    fn open(
+        mut transport: Box<dyn MidiTransport>,
+        saved: Option<SavedMode>,
+        known: Option<DeviceInfo>
+    ) -> Result<LaunchpadX, LaunchpadError> {
+        let (event, info) = device::listen(&mut *transport, known, LaunchpadX::parse_midi_message);
+
+        let mut launchpad = LaunchpadX {
+            transport, event, info, saved, closed: false, profile: None
+        };
+
+        launchpad.send(&protocol::programmer_mode(LaunchpadX::DEVICE_ID, true))?;
+        device::verify(&mut *launchpad.transport, known)?;
 
-        let in_port: &midir::MidiInputPort = LaunchpadX::get_input_device(&midi_in, &in_ports).unwrap();
+        Ok(launchpad)
+    }
 
-        println!("Opening connection");
-        let conn_in = midi_in.connect(in_port, "launchpad-api", move |_stamp, message, event| {
-            match LaunchpadX::parse_midi_message(message) {
-                Some(args) => event.lock().unwrap().trigger(args),
-                None => ()
+    /// Asks the device for its current layout and whether it is in Programmer mode.
+    fn read_mode(transport: &mut dyn MidiTransport, timeout: Duration) -> Result<SavedMode, LaunchpadError> {
+        let (sender, receiver) = mpsc::channel();
+        transport.listen(Box::new(move |_stamp, message| {
+            if let [0xF0, 0x00, 0x20, 0x29, 0x02, LaunchpadX::DEVICE_ID, command @ (0x00 | 0x0E), value, 0xF7] = message {
+                let _ = sender.send((*command, *value));
             }
-        }, event)?;
-        println!("Connection open.");
-        
-        Ok(conn_in)
-    }
-
-    pub fn get_output_device<'a>(midi_out: &midir::MidiOutput, out_ports: &'a midir::MidiOutputPorts) -> Result<&'a midir::MidiOutputPort, Box<dyn std::error::Error>> {
-        match out_ports.len() {
-            0 => return Err("No input port found.".into()),
-            1 => {
-                println!("Only available port: {}", midi_out.port_name(&out_ports[0]).unwrap());
-                Ok(&out_ports[0])},
-            _ => {
-                // if it matches the RegEx, then use that port.
-                let re = Regex::new(r"Launchpad X MIDI 2").unwrap();
-                let mut selected_port = None;
-                for port in out_ports.iter() {
-                    if re.is_match(&midi_out.port_name(port).unwrap()) {
-                        selected_port = Some(port);
-                    }
-                }
-
-                match selected_port {
-                    None => {
-                        // otherwise, prompt the user for the port.
-                        println!("Available output ports:");
-                        for (i, p) in out_ports.iter().enumerate() {
-                            println!("{}: {}", i, midi_out.port_name(p).unwrap());
-                        }
-                        print!("Please select output port: ");
-                        stdout().flush()?;
-                        let mut input = String::new();
-                        stdin().read_line(&mut input)?;
-                        out_ports.get(input.trim().parse::<usize>()?)
-                            .ok_or("Invalid output port selected.".into())
-                    },
-                    Some(port) => {
-                        Ok(port)
-                    }
-                }
+        }));
+
+        transport.send(&protocol::sysex(LaunchpadX::DEVICE_ID, &[0x00]))?; // read back the layout.
+        transport.send(&protocol::sysex(LaunchpadX::DEVICE_ID, &[0x0E]))?; // read back the mode.
+
+        let (mut layout, mut programmer) = (None, None);
+        while layout.is_none() || programmer.is_none() {
+            match receiver.recv_timeout(timeout) {
+                Ok((0x00, value)) => layout = Some(value),
+                Ok((_, value)) => programmer = Some(value != 0),
+                Err(_) => return Err(LaunchpadError::Timeout("no reply to mode query".to_string()))
             }
         }
+
+        Ok(SavedMode { layout: layout.unwrap(), programmer: programmer.unwrap() })
+    }
+
+    /// The layout and mode that closing the device returns to, if they were saved.
+    pub fn saved_mode(&self) -> Option<SavedMode> {
+        self.saved
     }
 
-    pub fn init_output_device() -> Result<midir::MidiOutputConnection, Box<dyn std::error::Error>> {
-        let midi_out = midir::MidiOutput::new("Launchpad Output")?;
-        
-        let out_ports = midi_out.ports();
-        let out_port: &midir::MidiOutputPort = LaunchpadX::get_output_device(&midi_out, &out_ports).unwrap();
+    /// Clears the grid and leaves Programmer mode, restoring the saved layout
+    /// if there is one. Dropping the device does the same, ignoring errors.
+    ///
+    /// The mode is restored even if the grid could not be cleared, and the
+    /// first error is returned.
+    pub fn close(&mut self) -> Result<(), LaunchpadError> {
+        if self.closed {
+            return Ok(());
+        }
 
-        println!("Opening connection");
-        let conn_out = midi_out.connect(out_port, "launchpad-api")?;
-        println!("Connection open.");
+        let cleared = self.clear_grid();
+        let restored = match self.saved {
+            Some(SavedMode { programmer: true, .. }) => Ok(()),
+            Some(SavedMode { layout, .. }) => {
+                self.send(&protocol::programmer_mode(LaunchpadX::DEVICE_ID, false))
+                    .and_then(|_| self.send(&protocol::select_layout(LaunchpadX::DEVICE_ID, layout)))
+            }
+            None => self.send(&protocol::programmer_mode(LaunchpadX::DEVICE_ID, false))
+        };
+        self.closed = true;
 
-        Ok(conn_out)
+        cleared.and(restored)
     }
 
-    pub fn send(&mut self, message: &[u8]) {
-        match self.output.send(message) {
-            Ok(_) => (),
-            Err(err) => println!("Error: {}", err)
-        };
+    pub fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+        self.transport.send(message)
     }
 
     pub fn parse_midi_message(message: &[u8]) -> Option<LaunchpadEventArgs> {
-        match message[0] {
-            0x80 => {
-                match LaunchpadX::lookup_layout(message[1]) {
-                    Some((x, y)) => Some(LaunchpadEventArgs::Released { x, y }),
-                    None => None
-                }
-            }
-            0x90 => {
-                match LaunchpadX::lookup_layout(message[1]) {
-                    Some((x, y)) => match message[2] {
-                        0 => Some(LaunchpadEventArgs::Released { x, y }),
-                        _ => Some(LaunchpadEventArgs::Pressed { x, y })
-                    },
-                    None => None
-                }
-            }
-            0xB0 => {
-                match LaunchpadX::lookup_layout(message[1]) {
-                    Some((x, y)) => match message[2] {
-                        0 => Some(LaunchpadEventArgs::Released { x, y }),
-                        _ => Some(LaunchpadEventArgs::Pressed { x, y })
-                    },
-                    None => None
-                }
-            }
-            _ => None
-        }
+        protocol::parse_message(&LaunchpadX::LED_LAYOUT, ButtonLayout::Standard, message)
+    }
+}
+
+impl Drop for LaunchpadX {
+    fn drop(&mut self) {
+        let _ = self.close();
     }
 }
 
 impl LaunchpadOutput for LaunchpadX {
-    fn set_light(&mut self, x: usize, y: usize, color: LaunchpadColor) {
-        let message = [
-            0xF0, // start a SysEx message.
-            0x00, 0x20, 0x29, 0x02, 0x0C, 0x03, // header for setting LaunchpadX color.
-            0x03, // use RGB color type.
-            LaunchpadX::LED_LAYOUT[y][x], // index the light using x and y.
-            color.red, color.green, color.blue, // set it to this color.
-            0xF7 // end a SysEx message.
-        ];
-
-        self.send(&message);
-    }
-
-    fn set_all_lights(&mut self, color: LaunchpadColor) {
-        let mut message = vec![
-            0xF0, // start a SysEx message.
-            0x00, 0x20, 0x29, 0x02, 0x0C, 0x03, // header for setting LaunchpadX color.
-        ];
-
-        for y in 0..9 {
-            for x in 0..9 {
-                message.push(0x03); // use RGB color type.
-                message.push(LaunchpadX::LED_LAYOUT[y][x]); // index the light using x and y.
-                message.append(&mut vec![color.red, color.green, color.blue]); // set it to this color.
-            }
-        }
+    fn grid_size(&self) -> (usize, usize) {
+        (9, 9)
+    }
+
+    fn has_light_modes(&self) -> bool {
+        true
+    }
+
+    fn set_color_profile(&mut self, profile: Option<ColorProfile>) {
+        self.profile = profile;
+    }
 
-        message.push(0xF7); // end a SysEx message.
+    fn set_brightness(&mut self, level: u8) -> Result<(), LaunchpadError> {
+        protocol::set_brightness(&mut *self.transport, LaunchpadX::DEVICE_ID, level)
+    }
 
-        self.send(&message);
+    fn brightness(&mut self) -> Result<u8, LaunchpadError> {
+        protocol::brightness(&mut *self.transport, &self.event, LaunchpadX::DEVICE_ID)
     }
 
-    fn set_state(&mut self, lights: LaunchpadState) {
-        let mut message = vec![
-            0xF0, // start a SysEx message.
-            0x00, 0x20, 0x29, 0x02, 0x0C, 0x03, // header for setting LaunchpadX color.
-        ];
+    fn set_sleeping(&mut self, sleeping: bool) -> Result<(), LaunchpadError> {
+        protocol::set_sleeping(&mut *self.transport, LaunchpadX::DEVICE_ID, sleeping)
+    }
 
-        for y in 0..9 {
-            for x in 0..9 {
-                let color = &lights.get_lights()[y][x];
+    fn is_sleeping(&mut self) -> Result<bool, LaunchpadError> {
+        protocol::is_sleeping(&mut *self.transport, &self.event, LaunchpadX::DEVICE_ID)
+    }
+
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        let notes = protocol::layout_lights(&LaunchpadX::LED_LAYOUT, lights)?;
+
+        self.send(&protocol::rgb_lights(LaunchpadX::DEVICE_ID, self.profile.as_ref(), &notes))
+    }
+
+    fn set_lights_with_mode(&mut self, lights: &[(usize, usize, LaunchpadColor, LightMode)]) -> Result<(), LaunchpadError> {
+        let notes = protocol::layout_mode_lights(&LaunchpadX::LED_LAYOUT, lights)?;
+
+        self.send(&protocol::mode_lights(LaunchpadX::DEVICE_ID, self.profile.as_ref(), &notes))
+    }
+}
+
+impl Launchpad for LaunchpadX {
+    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
 
-                message.push(0x03); // use RGB color type.
-                message.push(LaunchpadX::LED_LAYOUT[y][x]); // index the light using x and y.
-                message.append(&mut vec![color.red, color.green, color.blue]); // set it to this color.
+    fn device_info(&self) -> Option<DeviceInfo> {
+        *self.info.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, Recorder};
+    use crate::button::Button;
+    use crate::LaunchpadState;
+    use crate::transport::LoopbackTransport;
+
+    #[test]
+    fn parse_midi_message() {
+        assert_eq!(LaunchpadX::parse_midi_message(&[0x90, 11, 100]), Some(LaunchpadEventArgs::Pressed { x: 0, y: 8, button: Button::Grid { x: 0, y: 7 }, velocity: 100 }));
+        assert_eq!(LaunchpadX::parse_midi_message(&[0x90, 99, 0]), Some(LaunchpadEventArgs::Released { x: 8, y: 0, button: Button::Logo }));
+        assert_eq!(LaunchpadX::parse_midi_message(&[0xB0, 95, 127]), Some(LaunchpadEventArgs::Pressed { x: 4, y: 0, button: Button::Session, velocity: 127 }));
+        assert_eq!(LaunchpadX::parse_midi_message(&[0x90, 10, 127]), None);
+        assert_eq!(LaunchpadX::parse_midi_message(&[0xF8]), None);
+
+        assert_eq!(LaunchpadX::parse_midi_message(&[0xA0, 44, 90]), Some(LaunchpadEventArgs::Aftertouch { x: 3, y: 5, button: Button::Grid { x: 3, y: 4 }, pressure: 90 }));
+        assert_eq!(LaunchpadX::parse_midi_message(&[0xA0, 10, 90]), None);
+        assert_eq!(LaunchpadX::parse_midi_message(&[0xD0, 35]), Some(LaunchpadEventArgs::ChannelPressure { pressure: 35 }));
+        assert_eq!(LaunchpadX::parse_midi_message(&[0xD0]), None);
+    }
+
+    #[test]
+    fn enters_programmer_mode_and_asks_for_device_info() {
+        let transport = LoopbackTransport::new();
+        let launchpad = LaunchpadX::with_transport(Box::new(transport.clone())).unwrap();
+
+        assert_eq!(transport.sent(), vec![
+            vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x0E, 0x01, 0xF7],
+            device::DEVICE_INQUIRY.to_vec()
+        ]);
+        assert_eq!(launchpad.device_info(), None);
+
+        transport.receive(&[0xF0, 0x7E, 0x00, 0x06, 0x02, 0x00, 0x20, 0x29, 0x03, 0x01, 0x00, 0x00, 0x00, 0x04, 0x02, 0x07, 0xF7]);
+        assert_eq!(launchpad.device_info().unwrap().firmware_revision, Some(427));
+    }
+
+    #[test]
+    fn set_box_and_state() {
+        let (mut launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        launchpad.set_box(1, 7, 2, 1, LaunchpadColor::RED).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![
+            0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x03,
+            0x03, 22, 127, 0, 0,
+            0x03, 23, 127, 0, 0,
+            0xF7
+        ]]);
+
+        let mut state = LaunchpadState::new();
+        state.set_light(0, 0, LaunchpadColor::BLUE).unwrap();
+        launchpad.set_state(state).unwrap();
+
+        let sent = transport.take_sent();
+        assert_eq!(sent[0].len(), 7 + 81 * 5 + 1);
+        assert_eq!(&sent[0][7..12], &[0x03, 91, 0, 0, 127]);
+        assert_eq!(&sent[0][12..17], &[0x03, 92, 0, 0, 0]);
+    }
+
+    #[test]
+    fn sets_lighting_modes() {
+        let (mut launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        launchpad.set_lights_with_mode(&[
+            (0, 8, LaunchpadColor::BLACK, LightMode::Palette(5)),
+            (1, 8, LaunchpadColor::BLACK, LightMode::Flash { a: 5, b: 9 }),
+            (2, 8, LaunchpadColor::BLACK, LightMode::Pulse(45)),
+            (3, 8, LaunchpadColor::RED, LightMode::Rgb)
+        ]).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![
+            0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x03,
+            0x00, 11, 5,
+            0x01, 12, 9, 5,
+            0x02, 13, 45,
+            0x03, 14, 127, 0, 0,
+            0xF7
+        ]]);
+
+        let mut state = LaunchpadState::new();
+        state.set_light_with_mode(8, 0, LaunchpadColor::BLACK, LightMode::Pulse(21)).unwrap();
+        assert_eq!(state.get_mode(8, 0), Some(LightMode::Pulse(21)));
+        launchpad.set_state(state).unwrap();
+        assert_eq!(&transport.take_sent()[0][7 + 8 * 5..7 + 8 * 5 + 3], &[0x02, 99, 21]);
+    }
+
+    #[test]
+    fn rejects_palette_indices_outside_palette() {
+        let (mut launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        assert!(matches!(
+            launchpad.set_light_with_mode(0, 8, LaunchpadColor::BLACK, LightMode::Palette(200)),
+            Err(LaunchpadError::InvalidPaletteIndex(200))
+        ));
+        assert!(matches!(
+            launchpad.set_light_with_mode(0, 8, LaunchpadColor::BLACK, LightMode::Flash { a: 5, b: 128 }),
+            Err(LaunchpadError::InvalidPaletteIndex(128))
+        ));
+        assert!(transport.take_sent().is_empty());
+
+        let mut state = LaunchpadState::new();
+        assert!(state.set_light_with_mode(0, 0, LaunchpadColor::BLACK, LightMode::Pulse(255)).is_err());
+        assert_eq!(state.get_mode(0, 0), Some(LightMode::Rgb));
+    }
+
+    #[test]
+    fn applies_color_profile_to_rgb_lights() {
+        let (mut launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        launchpad.set_color_profile(Some(ColorProfile { gamma: [2.0, 1.0, 1.0], white_balance: [1.0, 1.0, 0.5] }));
+        launchpad.set_lights_with_mode(&[
+            (0, 8, LaunchpadColor { red: 64, green: 64, blue: 127 }, LightMode::Rgb),
+            (1, 8, LaunchpadColor::BLACK, LightMode::Pulse(45))
+        ]).unwrap();
+        assert_eq!(transport.take_sent(), vec![vec![
+            0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x03,
+            0x03, 11, 32, 64, 64,
+            0x02, 12, 45,
+            0xF7
+        ]]);
+
+        launchpad.set_color_profile(None);
+        launchpad.set_light(0, 8, LaunchpadColor { red: 64, green: 64, blue: 127 }).unwrap();
+        assert_eq!(transport.take_sent()[0][7..12], [0x03, 11, 64, 64, 127]);
+    }
+
+    #[test]
+    fn sets_and_queries_brightness_and_sleep() {
+        let (mut launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        launchpad.set_brightness(200).unwrap();
+        launchpad.sleep().unwrap();
+        launchpad.wake().unwrap();
+        assert_eq!(transport.take_sent(), vec![
+            vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x08, 127, 0xF7],
+            vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x09, 0x00, 0xF7],
+            vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x09, 0x01, 0xF7]
+        ]);
+
+        let device = transport.clone();
+        let responder = std::thread::spawn(move || {
+            while device.sent().is_empty() {
+                std::thread::yield_now();
             }
+            device.receive(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x09, 0x00, 0xF7]);
+            device.receive(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x08, 0x40, 0xF7]);
+        });
+
+        // the sleep reply arrives first, and must not be taken for the brightness.
+        assert_eq!(launchpad.brightness().unwrap(), 0x40);
+        responder.join().unwrap();
+        assert_eq!(transport.take_sent()[0], vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x08, 0xF7]);
+
+        assert!(matches!(launchpad.is_sleeping(), Err(LaunchpadError::Timeout(_))));
+    }
+
+    #[test]
+    fn fails_queries_from_event_handlers() {
+        let (launchpad, transport) = testing::open(LaunchpadX::with_transport);
+        let launchpad = Arc::new(Mutex::new(launchpad));
+
+        let (sender, results) = mpsc::channel();
+        let handle = launchpad.clone();
+        let subscription = launchpad.lock().unwrap().get_event().lock().unwrap().subscribe_fn(move |_, _| {
+            let _ = sender.send(handle.lock().unwrap().brightness());
+        });
+
+        transport.receive(&[0x90, 11, 127]);
+        assert!(matches!(results.try_recv(), Ok(Err(LaunchpadError::Timeout(_)))));
+        drop(subscription);
+    }
+
+    #[test]
+    fn incoming_messages_trigger_events() {
+        let (launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        launchpad.set_event_handler(Box::new(Recorder { events: events.clone() }));
+
+        transport.receive(&[0x90, 55, 64]);
+        transport.receive(&[0x80, 55, 0]);
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            LaunchpadEventArgs::Pressed { x: 4, y: 4, button: Button::Grid { x: 4, y: 3 }, velocity: 64 },
+            LaunchpadEventArgs::Released { x: 4, y: 4, button: Button::Grid { x: 4, y: 3 } }
+        ]);
+    }
+
+    type Stamped = (LaunchpadEventArgs, Option<u64>);
+
+    struct StampRecorder {
+        events: Arc<Mutex<Vec<Stamped>>>
+    }
+
+    impl LaunchpadEventHandler for StampRecorder {
+        fn notify(&self, _args: &LaunchpadEventArgs) {
+            unreachable!();
         }
 
-        message.push(0xF7); // end a SysEx message.
+        fn notify_at(&self, args: &LaunchpadEventArgs, time: EventTime) {
+            self.events.lock().unwrap().push((args.clone(), time.stamp));
+        }
+    }
+
+    #[test]
+    fn passes_on_unknown_messages_with_stamps() {
+        let (launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        launchpad.set_event_handler(Box::new(StampRecorder { events: events.clone() }));
 
-        self.send(&message);
+        transport.receive_at(1500, &[0x90, 55, 64]);
+        transport.receive_at(2000, &[0xC0, 5]);
+        transport.receive_at(2500, &[0xF8]);
+
+        assert_eq!(*events.lock().unwrap(), vec![
+            (LaunchpadEventArgs::Pressed { x: 4, y: 4, button: Button::Grid { x: 4, y: 3 }, velocity: 64 }, Some(1500)),
+            (LaunchpadEventArgs::Raw(vec![0xC0, 5]), Some(2000)),
+            (LaunchpadEventArgs::Raw(vec![0xF8]), Some(2500))
+        ]);
+    }
+
+    #[test]
+    fn close_clears_grid_and_returns_to_live_mode() {
+        let (mut launchpad, transport) = testing::open(LaunchpadX::with_transport);
+
+        launchpad.close().unwrap();
+        let sent = transport.take_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(&sent[0][7..12], &[0x03, 91, 0, 0, 0]);
+        assert_eq!(sent[1], vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x0E, 0x00, 0xF7]);
+
+        // dropping an already closed device sends nothing more.
+        drop(launchpad);
+        assert!(transport.sent().is_empty());
     }
 
-    fn set_box(&mut self, x: usize, y: usize, width: usize, height: usize, color: LaunchpadColor) {
-        let mut message = vec![
-            0xF0, // start a SysEx message.
-            0x00, 0x20, 0x29, 0x02, 0x0C, 0x03, // header for setting LaunchpadX color.
-        ];
+    /// Drops every RGB lights message, as if the device had gone away
+    /// halfway through.
+    struct FailingLights(LoopbackTransport);
 
-        for y in y..y+height {
-            for x in x..x+width {
-                message.push(0x03); // use RGB color type.
-                message.push(LaunchpadX::LED_LAYOUT[y][x]); // index the light using x and y.
-                message.append(&mut vec![color.red, color.green, color.blue]); // set it to this color.
+    impl MidiTransport for FailingLights {
+        fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+            match message.get(6) {
+                Some(0x03) => Err(LaunchpadError::SendFailed("lights".to_string())),
+                _ => self.0.send(message)
             }
         }
 
-        message.push(0xF7); // end a SysEx message.
-
-        self.send(&message);
+        fn listen(&mut self, callback: crate::transport::MidiCallback) {
+            self.0.listen(callback);
+        }
     }
 
-    fn clear_grid(&mut self) {
-        self.set_all_lights(LaunchpadColor::BLACK);
+    #[test]
+    fn close_leaves_programmer_mode_when_clearing_fails() {
+        let transport = LoopbackTransport::new();
+        let mut launchpad = LaunchpadX::with_transport(Box::new(FailingLights(transport.clone()))).unwrap();
+        transport.take_sent();
+
+        assert!(matches!(launchpad.close(), Err(LaunchpadError::SendFailed(_))));
+        assert_eq!(transport.take_sent(), vec![vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x0E, 0x00, 0xF7]]);
     }
-}
 
-impl Launchpad for LaunchpadX {
-    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
-        &self.event
+    #[test]
+    fn drop_restores_saved_layout() {
+        let transport = LoopbackTransport::new();
+
+        let device = transport.clone();
+        let responder = std::thread::spawn(move || {
+            while device.sent().len() < 2 {
+                std::thread::yield_now();
+            }
+            device.receive(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x00, 0x01, 0xF7]);
+            device.receive(&[0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x0E, 0x00, 0xF7]);
+        });
+
+        let launchpad = LaunchpadX::with_transport_saving_mode(Box::new(transport.clone()), Duration::from_secs(5)).unwrap();
+        responder.join().unwrap();
+        assert_eq!(launchpad.saved_mode(), Some(SavedMode { layout: 0x01, programmer: false }));
+
+        transport.take_sent();
+        drop(launchpad);
+
+        let sent = transport.take_sent();
+        assert_eq!(sent[1], vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x0E, 0x00, 0xF7]);
+        assert_eq!(sent[2], vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x00, 0x01, 0xF7]);
+
+        assert!(LaunchpadX::with_transport_saving_mode(Box::new(LoopbackTransport::new()), Duration::from_millis(10)).is_err());
     }
 
+    #[test]
+    fn clips_boxes_and_rejects_out_of_range_lights() {
+        let (mut launchpad, transport) = testing::open(LaunchpadX::with_transport);
 
-    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
-        &self.event.lock().unwrap().subscribe(handler);
+        launchpad.set_box(5, 5, 8, 8, LaunchpadColor::RED).unwrap();
+        let sent = transport.take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].len(), 7 + 16 * 5 + 1);
+
+        match launchpad.set_light(9, 0, LaunchpadColor::RED) {
+            Err(LaunchpadError::InvalidCoordinate { x: 9, y: 0 }) => (),
+            other => panic!("unexpected result: {:?}", other)
+        }
+        assert!(launchpad.set_lights(&[(0, 0, LaunchpadColor::RED), (0, 9, LaunchpadColor::RED)]).is_err());
+        assert!(transport.take_sent().is_empty());
     }
-}
\ No newline at end of file
+}
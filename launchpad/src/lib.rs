@@ -1,35 +1,305 @@
 #![allow(dead_code)]
 
+pub mod button;
+pub mod pad;
 pub mod color;
+pub mod palette;
+pub mod error;
 pub mod state;
 pub mod event;
+pub mod transport;
+pub mod selector;
+pub mod mock;
+pub mod device;
+pub mod watcher;
+pub mod gesture;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+
+pub(crate) mod protocol;
 
 pub mod launchpad_x;
+pub mod launchpad_mini_mk3;
+pub mod launchpad_pro_mk3;
+pub mod launchpad_mk2;
+pub mod launchpad_s;
+
+#[cfg(test)]
+mod testing;
 
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
 
-use launchpad_x::LaunchpadX;
-use color::LaunchpadColor;
+use device::{identify, DeviceInfo, LaunchpadDescriptor, LaunchpadModel};
+use transport::MidirTransport;
+use button::{Button, ButtonLayout};
+use pad::Pad;
+use color::{ColorProfile, LaunchpadColor, LightMode};
 use state::LaunchpadState;
 use event::*;
+use error::LaunchpadError;
+use selector::PortSelector;
 
 pub trait LaunchpadOutput {
-    fn set_all_lights(&mut self, color: LaunchpadColor);
-    fn set_light(&mut self, x: usize, y: usize, color: LaunchpadColor);
-    fn set_state(&mut self, lights: LaunchpadState);
-    fn clear_grid(&mut self);
-    fn set_box(&mut self, x: usize, y: usize, width: usize, height: usize, color: LaunchpadColor);
+    /// Width and height of the grid of lights, including any side buttons.
+    fn grid_size(&self) -> (usize, usize);
+
+    /// Which `Button` is where in the grid of lights.
+    fn button_layout(&self) -> ButtonLayout {
+        ButtonLayout::Standard
+    }
+
+    /// Whether there is a light at `(x, y)`. Layouts with gaps in their grid
+    /// override this.
+    fn has_light(&self, x: usize, y: usize) -> bool {
+        let (width, height) = self.grid_size();
+        x < width && y < height
+    }
+
+    /// The pad at `(x, y)`, if there is a light there.
+    fn pad(&self, x: usize, y: usize) -> Option<Pad> {
+        if self.has_light(x, y) {
+            Some(Pad::new(x, y))
+        } else {
+            None
+        }
+    }
+
+    /// The pad `dx` across and `dy` down from `pad`, if there is a light there.
+    fn offset_pad(&self, pad: Pad, dx: isize, dy: isize) -> Option<Pad> {
+        let x = pad.x().checked_add_signed(dx)?;
+        let y = pad.y().checked_add_signed(dy)?;
+        self.pad(x, y)
+    }
+
+    /// Sets each `(x, y, color)` light in one update. If any coordinate is
+    /// outside the grid, nothing is changed.
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError>;
+
+    /// Whether lights can be shown in modes other than `LightMode::Rgb`.
+    fn has_light_modes(&self) -> bool {
+        false
+    }
+
+    /// Sets each `(x, y, color, mode)` light in one update. `LightMode::Rgb`
+    /// shows `color`; the other modes use palette colors and ignore it.
+    ///
+    /// Devices without lighting modes show the palette modes as their static
+    /// palette color, using the first color of a flash. Palette indices above
+    /// 127 are rejected. Nothing is changed on error.
+    fn set_lights_with_mode(&mut self, lights: &[(usize, usize, LaunchpadColor, LightMode)]) -> Result<(), LaunchpadError> {
+        let mut rgb = Vec::with_capacity(lights.len());
+        for (x, y, color, mode) in lights.iter() {
+            mode.validate()?;
+            let color = match *mode {
+                LightMode::Rgb => Some(*color),
+                LightMode::Palette(index) | LightMode::Pulse(index) | LightMode::Flash { a: index, .. } => LaunchpadColor::from_palette(index)
+            };
+            rgb.push((*x, *y, color.ok_or(LaunchpadError::UnsupportedLightMode(*mode))?));
+        }
+        self.set_lights(&rgb)
+    }
+
+    fn set_light_with_mode(&mut self, x: usize, y: usize, color: LaunchpadColor, mode: LightMode) -> Result<(), LaunchpadError> {
+        self.set_lights_with_mode(&[(x, y, color, mode)])
+    }
+
+    /// Corrects every color sent from now on with `profile`, or sends colors
+    /// as they are if it is `None`. States and mocks keep colors as given.
+    fn set_color_profile(&mut self, _profile: Option<ColorProfile>) {}
+
+    /// Sets the brightness of every LED, from 0 to 127.
+    fn set_brightness(&mut self, _level: u8) -> Result<(), LaunchpadError> {
+        Err(LaunchpadError::UnsupportedFeature("brightness".to_string()))
+    }
+
+    /// Asks the device for its LED brightness, waiting for the reply.
+    ///
+    /// Must not be called from an event handler, as the reply is delivered
+    /// by the thread running it. Devices report this as
+    /// `LaunchpadError::Timeout` once the query times out.
+    fn brightness(&mut self) -> Result<u8, LaunchpadError> {
+        Err(LaunchpadError::UnsupportedFeature("brightness".to_string()))
+    }
+
+    /// Turns every LED off, or back on again, without changing their colors.
+    fn set_sleeping(&mut self, _sleeping: bool) -> Result<(), LaunchpadError> {
+        Err(LaunchpadError::UnsupportedFeature("sleep mode".to_string()))
+    }
+
+    /// Asks the device whether its LEDs are asleep, waiting for the reply.
+    ///
+    /// Like `brightness`, must not be called from an event handler.
+    fn is_sleeping(&mut self) -> Result<bool, LaunchpadError> {
+        Err(LaunchpadError::UnsupportedFeature("sleep mode".to_string()))
+    }
+
+    fn sleep(&mut self) -> Result<(), LaunchpadError> {
+        self.set_sleeping(true)
+    }
+
+    fn wake(&mut self) -> Result<(), LaunchpadError> {
+        self.set_sleeping(false)
+    }
+
+    fn set_all_lights(&mut self, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        let (width, height) = self.grid_size();
+        self.set_box(0, 0, width, height, color)
+    }
+
+    fn set_light(&mut self, x: usize, y: usize, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        self.set_lights(&[(x, y, color)])
+    }
+
+    /// Sets the light of a named button or grid pad.
+    ///
+    /// This is a separate method rather than `set_light` taking either form,
+    /// as a generic `set_light` could not be called on a `dyn Launchpad`.
+    fn set_button_light(&mut self, button: Button, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        let (x, y) = self.button_layout().position(button)
+            .ok_or(LaunchpadError::InvalidButton(button))?;
+        self.set_light(x, y, color)
+    }
+
+    fn set_pad_light(&mut self, pad: Pad, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        self.set_light(pad.x(), pad.y(), color)
+    }
+
+    /// Sets every light to its color and mode in `lights`, skipping any the
+    /// device does not have.
+    fn set_state(&mut self, lights: LaunchpadState) -> Result<(), LaunchpadError> {
+        let mut all = Vec::with_capacity(lights.width() * lights.height());
+        for y in 0..lights.height() {
+            for x in 0..lights.width() {
+                if let (true, Some(color), Some(mode)) = (self.has_light(x, y), lights.get_light(x, y), lights.get_mode(x, y)) {
+                    all.push((x, y, color, mode));
+                }
+            }
+        }
+        self.set_lights_with_mode(&all)
+    }
+
+    fn clear_grid(&mut self) -> Result<(), LaunchpadError> {
+        self.set_all_lights(LaunchpadColor::BLACK)
+    }
+
+    /// Sets the lights in the box at `(x, y)`. The box is clipped to the
+    /// lights the device has, so any part of it outside the grid is ignored.
+    fn set_box(&mut self, x: usize, y: usize, width: usize, height: usize, color: LaunchpadColor) -> Result<(), LaunchpadError> {
+        let (grid_width, grid_height) = self.grid_size();
+
+        let mut lights = Vec::new();
+        for y in y..y.saturating_add(height).min(grid_height) {
+            for x in x..x.saturating_add(width).min(grid_width) {
+                if self.has_light(x, y) {
+                    lights.push((x, y, color));
+                }
+            }
+        }
+
+        if lights.is_empty() {
+            return Ok(());
+        }
+        self.set_lights(&lights)
+    }
 }
 
 pub trait Launchpad: LaunchpadOutput + Send {
     fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>>;
     fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>);
+
+    /// Returns a receiver for every event from now on, so an application can
+    /// run its own event loop instead of implementing a handler.
+    fn events(&self) -> mpsc::Receiver<TimedEvent> {
+        self.get_event().lock().unwrap().channel()
+    }
+
+    /// The model and firmware the device reported, once it has answered the
+    /// Device Inquiry sent when it was opened.
+    fn device_info(&self) -> Option<DeviceInfo>;
 }
 
-pub fn create_launchpad() -> Result<Box<dyn Launchpad>, Box<dyn std::error::Error>> {        
-    let launchpad = LaunchpadX::init()?;
+/// How long to wait for a port to answer the Device Inquiry.
+const INQUIRY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Connects to the output port named `name`, and the input port of the same
+/// device, and asks what is on the other end.
+fn probe(name: &str) -> Option<(MidirTransport, DeviceInfo)> {
+    let mut transport = MidirTransport::connect(&PortSelector::Name(name.to_string())).ok()?;
+    let info = identify(&mut transport, INQUIRY_TIMEOUT).ok()?;
 
-    Ok(launchpad)
+    if info.model.is_control_port(name) {
+        Some((transport, info))
+    } else {
+        None
+    }
+}
+
+/// Opens the first Launchpad that answers a Device Inquiry, trying ports
+/// whose names look like a Launchpad first. If `LAUNCHPAD_PORT` is set, only
+/// that port is tried.
+pub fn create_launchpad() -> Result<Box<dyn Launchpad>, LaunchpadError> {
+    if std::env::var(PortSelector::ENV_VAR).is_ok() {
+        return create_launchpad_with(&PortSelector::Env(PortSelector::ENV_VAR.to_string()));
+    }
+
+    let names = MidirTransport::port_names()?;
+    if names.is_empty() {
+        return Err(LaunchpadError::NoPorts);
+    }
+
+    for name in probe_order(names) {
+        if let Some((transport, info)) = probe(&name) {
+            return info.model.open(Box::new(transport), Some(info));
+        }
+    }
+
+    Err(LaunchpadError::PortNotFound("no port answered the device inquiry".to_string()))
+}
+
+/// Output port names with those that look like a Launchpad first.
+fn probe_order(mut names: Vec<String>) -> Vec<String> {
+    let launchpads = PortSelector::launchpad_ports();
+    names.sort_by_key(|name| launchpads.select(std::slice::from_ref(name)).is_err());
+    names
+}
+
+/// Finds every connected Launchpad by sending a Device Inquiry on each port.
+///
+/// The ports are closed again afterwards; pass a descriptor to `open` to use it.
+pub fn list_launchpads() -> Result<Vec<LaunchpadDescriptor>, LaunchpadError> {
+    let mut launchpads = Vec::new();
+    for port_name in MidirTransport::port_names()? {
+        if let Some((_, info)) = probe(&port_name) {
+            launchpads.push(LaunchpadDescriptor { port_name, info });
+        }
+    }
+    Ok(launchpads)
+}
+
+/// Opens a Launchpad found by `list_launchpads`. Every handle has its own
+/// `LaunchpadEvent`, so several can be driven from one process.
+pub fn open(descriptor: &LaunchpadDescriptor) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+    let transport = MidirTransport::connect(&PortSelector::Name(descriptor.port_name.clone()))?;
+
+    descriptor.model().open(Box::new(transport), Some(descriptor.info))
+}
+
+/// Opens the port chosen by `selector` and builds the backend for the model
+/// it reports, falling back to its port name if it does not answer.
+pub fn create_launchpad_with(selector: &PortSelector) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+    let mut transport = MidirTransport::connect(selector)?;
+
+    let (model, info) = match identify(&mut transport, INQUIRY_TIMEOUT) {
+        Ok(info) => (info.model, Some(info)),
+        Err(_) => {
+            let model = LaunchpadModel::from_port_name(transport.port_name())
+                .ok_or_else(|| LaunchpadError::UnsupportedDevice(transport.port_name().to_string()))?;
+            (model, None)
+        }
+    };
+
+    model.open(Box::new(transport), info)
 }
 
 #[cfg(test)]
@@ -40,4 +310,10 @@ pub mod tests {
     pub fn color_constants() {
         assert_eq!(LaunchpadColor::BLACK.red, 0)
     }
+
+    #[test]
+    fn probes_launchpad_ports_first() {
+        let names = vec!["Some Synth".to_string(), "MIDIOUT2 (LPX MIDI)".to_string(), "Launchpad MK2".to_string()];
+        assert_eq!(probe_order(names), vec!["MIDIOUT2 (LPX MIDI)", "Launchpad MK2", "Some Synth"]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,210 @@
+use crate::{Launchpad, LaunchpadOutput, LaunchpadColor, LaunchpadState};
+use crate::color::LightMode;
+use crate::button::{Button, ButtonLayout};
+use crate::event::*;
+use crate::device::DeviceInfo;
+use crate::error::LaunchpadError;
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// A `Launchpad` with no device behind it, for testing applications.
+///
+/// Light changes are applied to an in-memory `LaunchpadState`, and pad presses
+/// can be injected through the same `LaunchpadEvent` a real device triggers.
+/// Clones share the same state and event, so keep one clone to drive the test
+/// after handing another to the application.
+#[derive(Clone)]
+pub struct MockLaunchpad {
+    state: Arc<Mutex<LaunchpadState>>,
+    event: Arc<Mutex<LaunchpadEvent>>,
+    buttons: ButtonLayout,
+
+    brightness: Arc<AtomicU8>,
+    sleeping: Arc<AtomicBool>
+}
+
+impl MockLaunchpad {
+    /// Creates a mock with a 9x9 grid, like a Launchpad X.
+    pub fn new() -> MockLaunchpad {
+        MockLaunchpad::with_size(9, 9, ButtonLayout::Standard)
+    }
+
+    /// Creates a mock with a grid of any size, naming its buttons after `buttons`.
+    pub fn with_size(width: usize, height: usize, buttons: ButtonLayout) -> MockLaunchpad {
+        MockLaunchpad {
+            state: Arc::new(Mutex::new(LaunchpadState::with_size(width, height))),
+            event: Arc::new(Mutex::new(LaunchpadEvent::default())),
+            buttons,
+            brightness: Arc::new(AtomicU8::new(127)),
+            sleeping: Arc::new(AtomicBool::new(false))
+        }
+    }
+
+    /// Returns a copy of the lights as they currently are.
+    pub fn get_state(&self) -> LaunchpadState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Returns the color of one light.
+    ///
+    /// Panics if `(x, y)` is outside the grid.
+    pub fn get_light(&self, x: usize, y: usize) -> LaunchpadColor {
+        self.state.lock().unwrap().get_light(x, y).expect("coordinate outside the grid")
+    }
+
+    pub fn trigger(&self, args: LaunchpadEventArgs) {
+        self.event.lock().unwrap().trigger(args);
+    }
+
+    pub fn press(&self, x: usize, y: usize) {
+        self.trigger(LaunchpadEventArgs::Pressed { x, y, button: self.buttons.button_at(x, y), velocity: 127 });
+    }
+
+    pub fn release(&self, x: usize, y: usize) {
+        self.trigger(LaunchpadEventArgs::Released { x, y, button: self.buttons.button_at(x, y) });
+    }
+
+    /// Presses a named button. Panics if the grid has no such button.
+    pub fn press_button(&self, button: Button) {
+        let (x, y) = self.buttons.position(button).expect("no such button");
+        self.press(x, y);
+    }
+
+    /// Releases a named button. Panics if the grid has no such button.
+    pub fn release_button(&self, button: Button) {
+        let (x, y) = self.buttons.position(button).expect("no such button");
+        self.release(x, y);
+    }
+}
+
+impl Default for MockLaunchpad {
+    fn default() -> MockLaunchpad {
+        MockLaunchpad::new()
+    }
+}
+
+impl LaunchpadOutput for MockLaunchpad {
+    fn grid_size(&self) -> (usize, usize) {
+        self.state.lock().unwrap().grid_size()
+    }
+
+    fn button_layout(&self) -> ButtonLayout {
+        self.buttons
+    }
+
+    fn has_light_modes(&self) -> bool {
+        true
+    }
+
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        self.state.lock().unwrap().set_lights(lights)
+    }
+
+    fn set_lights_with_mode(&mut self, lights: &[(usize, usize, LaunchpadColor, LightMode)]) -> Result<(), LaunchpadError> {
+        self.state.lock().unwrap().set_lights_with_mode(lights)
+    }
+
+    fn set_brightness(&mut self, level: u8) -> Result<(), LaunchpadError> {
+        self.brightness.store(level.min(127), Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn brightness(&mut self) -> Result<u8, LaunchpadError> {
+        Ok(self.brightness.load(Ordering::SeqCst))
+    }
+
+    fn set_sleeping(&mut self, sleeping: bool) -> Result<(), LaunchpadError> {
+        self.sleeping.store(sleeping, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn is_sleeping(&mut self) -> Result<bool, LaunchpadError> {
+        Ok(self.sleeping.load(Ordering::SeqCst))
+    }
+
+    fn set_state(&mut self, state: LaunchpadState) -> Result<(), LaunchpadError> {
+        self.state.lock().unwrap().set_state(state)
+    }
+}
+
+impl Launchpad for MockLaunchpad {
+    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
+
+    fn device_info(&self) -> Option<DeviceInfo> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Painter {
+        launchpad: Arc<Mutex<Box<dyn Launchpad>>>
+    }
+
+    impl LaunchpadEventHandler for Painter {
+        fn notify(&self, args: &LaunchpadEventArgs) {
+            if let LaunchpadEventArgs::Pressed { x, y, .. } = args {
+                self.launchpad.lock().unwrap().set_light(*x, *y, LaunchpadColor::GREEN).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn records_lights_from_handlers() {
+        let mock = MockLaunchpad::new();
+        let launchpad: Arc<Mutex<Box<dyn Launchpad>>> = Arc::new(Mutex::new(Box::new(mock.clone())));
+        launchpad.lock().unwrap().set_event_handler(Box::new(Painter { launchpad: launchpad.clone() }));
+
+        launchpad.lock().unwrap().set_box(0, 0, 2, 2, LaunchpadColor::RED).unwrap();
+        mock.press(1, 1);
+
+        assert_eq!(mock.get_light(0, 0).red, 127);
+        assert_eq!(mock.get_light(1, 1).green, 127);
+        assert_eq!(mock.get_light(1, 1).red, 0);
+        assert_eq!(mock.get_light(2, 2).red, 0);
+    }
+
+    #[test]
+    fn delivers_events_to_channel() {
+        let mock = MockLaunchpad::new();
+        let events = mock.events();
+
+        mock.press(3, 3);
+        mock.release(3, 3);
+        drop(mock);
+
+        assert_eq!(events.iter().map(|event| event.args).collect::<Vec<_>>(), vec![
+            LaunchpadEventArgs::Pressed { x: 3, y: 3, button: Button::Grid { x: 3, y: 2 }, velocity: 127 },
+            LaunchpadEventArgs::Released { x: 3, y: 3, button: Button::Grid { x: 3, y: 2 } }
+        ]);
+    }
+
+    #[test]
+    fn names_buttons_after_given_layout() {
+        let mock = MockLaunchpad::with_size(10, 10, ButtonLayout::Pro);
+        let events = mock.events();
+
+        mock.press_button(Button::Logo);
+        assert_eq!(events.try_recv().unwrap().args, LaunchpadEventArgs::Pressed { x: 9, y: 0, button: Button::Logo, velocity: 127 });
+    }
+
+    #[test]
+    fn remembers_brightness_and_sleep() {
+        let mock = MockLaunchpad::new();
+        let mut launchpad: Box<dyn Launchpad> = Box::new(mock.clone());
+
+        launchpad.set_brightness(64).unwrap();
+        launchpad.sleep().unwrap();
+        assert_eq!(mock.clone().brightness().unwrap(), 64);
+        assert!(mock.clone().is_sleeping().unwrap());
+    }
+}
@@ -0,0 +1,60 @@
+/// A grid position checked to have a light, made by `LaunchpadOutput::pad`.
+///
+/// Each model has its own grid, so a pad is only known to be valid on the
+/// launchpad or state that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pad {
+    x: usize,
+    y: usize
+}
+
+impl Pad {
+    pub(crate) fn new(x: usize, y: usize) -> Pad {
+        Pad { x, y }
+    }
+
+    pub fn x(self) -> usize {
+        self.x
+    }
+
+    pub fn y(self) -> usize {
+        self.y
+    }
+
+    pub fn position(self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LaunchpadOutput, LaunchpadColor, LaunchpadState};
+
+    #[test]
+    fn only_makes_pads_inside_the_grid() {
+        let mut state = LaunchpadState::new();
+
+        let corner = state.pad(8, 8).unwrap();
+        assert_eq!(corner.position(), (8, 8));
+        assert_eq!(state.pad(9, 0), None);
+
+        assert_eq!(state.offset_pad(corner, -8, -1).map(|pad| pad.position()), Some((0, 7)));
+        assert_eq!(state.offset_pad(corner, 1, 0), None);
+        assert_eq!(state.offset_pad(corner, 0, -9), None);
+
+        state.set_pad_light(corner, LaunchpadColor::RED).unwrap();
+        assert_eq!(state.get_light(8, 8).map(|color| color.red), Some(127));
+    }
+
+    #[test]
+    fn clips_boxes_to_the_grid() {
+        let mut state = LaunchpadState::new();
+
+        state.set_box(7, 7, 5, usize::MAX, LaunchpadColor::GREEN).unwrap();
+        assert_eq!(state.get_light(7, 8).map(|color| color.green), Some(127));
+        assert_eq!(state.get_light(8, 7).map(|color| color.green), Some(127));
+        assert_eq!(state.get_light(6, 7).map(|color| color.green), Some(0));
+
+        state.set_box(20, 20, 2, 2, LaunchpadColor::GREEN).unwrap();
+    }
+}
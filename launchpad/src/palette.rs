@@ -0,0 +1,96 @@
+//! The Launchpad X's built-in palette, used by the palette lighting modes
+//! and by note-on velocities in the device's note and custom modes.
+
+use crate::color::LaunchpadColor;
+
+/// Palette colors are published as 8 bit RGB.
+const fn rgb(hex: u32) -> LaunchpadColor {
+    LaunchpadColor::from_u32(hex)
+}
+
+/// The 128 palette colors of the Launchpad X, by index, eight to a row.
+pub const PALETTE: [LaunchpadColor; 128] = [
+    rgb(0x000000), rgb(0x1E1E1E), rgb(0x7F7F7F), rgb(0xFFFFFF), rgb(0xFF4C4C), rgb(0xFF0000), rgb(0x590000), rgb(0x190000),
+    rgb(0xFFBD6C), rgb(0xFF5400), rgb(0x591D00), rgb(0x271B00), rgb(0xFFFF4C), rgb(0xFFFF00), rgb(0x595900), rgb(0x191900),
+    rgb(0x88FF4C), rgb(0x54FF00), rgb(0x1D5900), rgb(0x142B00), rgb(0x4CFF4C), rgb(0x00FF00), rgb(0x005900), rgb(0x001900),
+    rgb(0x4CFF5E), rgb(0x00FF19), rgb(0x00590D), rgb(0x001902), rgb(0x4CFF88), rgb(0x00FF55), rgb(0x00591D), rgb(0x001F12),
+    rgb(0x4CFFB7), rgb(0x00FF99), rgb(0x005935), rgb(0x001912), rgb(0x4CC3FF), rgb(0x00A9FF), rgb(0x004152), rgb(0x001019),
+    rgb(0x4C88FF), rgb(0x0055FF), rgb(0x001D59), rgb(0x000819), rgb(0x4C4CFF), rgb(0x0000FF), rgb(0x000059), rgb(0x000019),
+    rgb(0x874CFF), rgb(0x5400FF), rgb(0x190064), rgb(0x0F0030), rgb(0xFF4CFF), rgb(0xFF00FF), rgb(0x590059), rgb(0x190019),
+    rgb(0xFF4C87), rgb(0xFF0054), rgb(0x59001D), rgb(0x220013), rgb(0xFF1500), rgb(0x993500), rgb(0x795100), rgb(0x436400),
+    rgb(0x033900), rgb(0x005735), rgb(0x00547F), rgb(0x0000FF), rgb(0x00454F), rgb(0x2500CC), rgb(0x7F7F7F), rgb(0x202020),
+    rgb(0xFF0000), rgb(0xBDFF2D), rgb(0xAFED06), rgb(0x64FF09), rgb(0x108B00), rgb(0x00FF87), rgb(0x00A9FF), rgb(0x002AFF),
+    rgb(0x3F00FF), rgb(0x7A00FF), rgb(0xB21A7D), rgb(0x402100), rgb(0xFF4A00), rgb(0x88E106), rgb(0x72FF15), rgb(0x00FF00),
+    rgb(0x3BFF26), rgb(0x59FF71), rgb(0x38FFCC), rgb(0x5B8AFF), rgb(0x3151C6), rgb(0x877FE9), rgb(0xD31DFF), rgb(0xFF005D),
+    rgb(0xFF7F00), rgb(0xB9B000), rgb(0x90FF00), rgb(0x835D07), rgb(0x392B00), rgb(0x144C10), rgb(0x0D5038), rgb(0x15152A),
+    rgb(0x16205A), rgb(0x693C1C), rgb(0xA8000A), rgb(0xDE513D), rgb(0xD86A1C), rgb(0xFFE126), rgb(0x9EE12F), rgb(0x67B50F),
+    rgb(0x1E1E30), rgb(0xDCFF6B), rgb(0x80FFBD), rgb(0x9A99FF), rgb(0x8E66FF), rgb(0x404040), rgb(0x757575), rgb(0xE0FFFF),
+    rgb(0xA00000), rgb(0x350000), rgb(0x1AD000), rgb(0x074200), rgb(0xB9B000), rgb(0x3F3100), rgb(0xB35F00), rgb(0x4B1502),
+];
+
+/// A color in the OKLab color space, where distances follow how different
+/// colors look.
+#[derive(Clone, Copy)]
+struct Lab {
+    l: f32,
+    a: f32,
+    b: f32
+}
+
+impl Lab {
+    fn from_color(color: LaunchpadColor) -> Lab {
+        let linear = |channel: u8| {
+            let c = f32::from(channel.min(127)) / 127.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        };
+        let (r, g, b) = (linear(color.red), linear(color.green), linear(color.blue));
+
+        let l = (0.412_221_47 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+        Lab {
+            l: 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            a: 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            b: 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s
+        }
+    }
+
+    fn distance(self, other: Lab) -> f32 {
+        (self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2)
+    }
+}
+
+/// The index of the palette color that looks closest to `color`. Where the
+/// palette repeats a color, the lowest index is used.
+pub(crate) fn nearest(color: LaunchpadColor) -> u8 {
+    let target = Lab::from_color(color);
+
+    let mut best = (0, f32::INFINITY);
+    for (index, entry) in PALETTE.iter().enumerate() {
+        let distance = target.distance(Lab::from_color(*entry));
+        if distance < best.1 {
+            best = (index, distance);
+        }
+    }
+    best.0 as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_nearest_palette_colors() {
+        assert_eq!(LaunchpadColor::BLACK.to_palette(), 0);
+        assert_eq!(LaunchpadColor::RED.to_palette(), 5);
+        assert_eq!(LaunchpadColor::GREEN.to_palette(), 21);
+        assert_eq!(LaunchpadColor { red: 120, green: 0, blue: 2 }.to_palette(), 5);
+
+        for index in 0..128 {
+            let color = LaunchpadColor::from_palette(index).unwrap();
+            assert_eq!(LaunchpadColor::from_palette(color.to_palette()), Some(color));
+        }
+        assert!(LaunchpadColor::from_palette(128).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! Pieces of the Novation MIDI protocol shared by several devices.
+
+use std::sync::{mpsc, Arc, Mutex, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::button::ButtonLayout;
+use crate::color::{ColorProfile, LaunchpadColor, LightMode};
+use crate::device::DeviceInfo;
+use crate::error::LaunchpadError;
+use crate::event::{LaunchpadEvent, LaunchpadEventArgs};
+use crate::transport::MidiTransport;
+
+/// Marks a position in a layout that has no pad. Never a valid MIDI data byte.
+pub(crate) const NO_PAD: u8 = 0xFF;
+
+/// Builds a Novation SysEx message for `device` around `payload`.
+pub(crate) fn sysex(device: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![
+        0xF0, // start a SysEx message.
+        0x00, 0x20, 0x29, // Novation manufacturer id.
+        0x02, device // product family and device.
+    ];
+    message.extend_from_slice(payload);
+    message.push(0xF7); // end a SysEx message.
+    message
+}
+
+/// Switches between Live mode and Programmer mode.
+pub(crate) fn programmer_mode(device: u8, enabled: bool) -> Vec<u8> {
+    sysex(device, &[0x0E, enabled as u8])
+}
+
+/// Selects a layout, such as Session, Note or Programmer, while in Live mode.
+pub(crate) fn select_layout(device: u8, layout: u8) -> Vec<u8> {
+    sysex(device, &[0x00, layout])
+}
+
+/// How long to wait for the device to answer a `query`.
+pub(crate) const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// SysEx command for the brightness of every LED, from 0 to 127.
+const BRIGHTNESS: u8 = 0x08;
+
+/// SysEx command for LED sleep mode, where 0 turns every LED off and 1 turns
+/// them back on.
+const LED_SLEEP: u8 = 0x09;
+
+/// Sends a read back request for `command` and waits for the value in the
+/// reply, which arrives through `event` as a `Raw` message.
+///
+/// Must not be called from an event handler, which would wait on itself. The
+/// event stays locked while a handler runs, so this is reported as a
+/// `Timeout` rather than waiting forever.
+pub(crate) fn query(
+    transport: &mut dyn MidiTransport,
+    event: &Arc<Mutex<LaunchpadEvent>>,
+    device: u8,
+    command: u8
+) -> Result<u8, LaunchpadError> {
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut event = loop {
+        match event.try_lock() {
+            Ok(event) => break event,
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => thread::sleep(Duration::from_millis(1)),
+            Err(TryLockError::WouldBlock) => {
+                return Err(LaunchpadError::Timeout("events are busy; queries cannot be made from an event handler".to_string()));
+            }
+            Err(TryLockError::Poisoned(err)) => panic!("{}", err)
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let _subscription = event.subscribe_fn(move |args, _| {
+        if let LaunchpadEventArgs::Raw(message) = args {
+            if let [0xF0, 0x00, 0x20, 0x29, 0x02, id, reply, value, 0xF7] = message[..] {
+                if id == device && reply == command {
+                    let _ = sender.send(value);
+                }
+            }
+        }
+    });
+    drop(event);
+
+    transport.send(&sysex(device, &[command]))?;
+    receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .map_err(|_| LaunchpadError::Timeout(format!("no reply to query {:#04X}", command)))
+}
+
+/// Sets the brightness of every LED on `device`, clamped to 127.
+pub(crate) fn set_brightness(transport: &mut dyn MidiTransport, device: u8, level: u8) -> Result<(), LaunchpadError> {
+    transport.send(&sysex(device, &[BRIGHTNESS, level.min(127)]))
+}
+
+/// Asks `device` for its LED brightness.
+pub(crate) fn brightness(
+    transport: &mut dyn MidiTransport,
+    event: &Arc<Mutex<LaunchpadEvent>>,
+    device: u8
+) -> Result<u8, LaunchpadError> {
+    query(transport, event, device, BRIGHTNESS)
+}
+
+/// Turns every LED on `device` off, or back on.
+pub(crate) fn set_sleeping(transport: &mut dyn MidiTransport, device: u8, sleeping: bool) -> Result<(), LaunchpadError> {
+    transport.send(&sysex(device, &[LED_SLEEP, u8::from(!sleeping)]))
+}
+
+/// Asks `device` whether its LEDs are asleep.
+pub(crate) fn is_sleeping(
+    transport: &mut dyn MidiTransport,
+    event: &Arc<Mutex<LaunchpadEvent>>,
+    device: u8
+) -> Result<bool, LaunchpadError> {
+    Ok(query(transport, event, device, LED_SLEEP)? == 0)
+}
+
+/// Sets each pad to an RGB colour in one message.
+pub(crate) fn rgb_lights(device: u8, profile: Option<&ColorProfile>, lights: &[(u8, LaunchpadColor)]) -> Vec<u8> {
+    let lights: Vec<_> = lights.iter().map(|(note, color)| (*note, *color, LightMode::Rgb)).collect();
+    mode_lights(device, profile, &lights)
+}
+
+/// Sets each pad to a colour in its lighting mode in one message, correcting
+/// RGB colours with `profile`.
+pub(crate) fn mode_lights(device: u8, profile: Option<&ColorProfile>, lights: &[(u8, LaunchpadColor, LightMode)]) -> Vec<u8> {
+    let mut payload = vec![0x03]; // LED lighting command.
+
+    for (note, color, mode) in lights.iter() {
+        let color = calibrate(profile, *color);
+        match mode {
+            LightMode::Palette(index) => payload.extend_from_slice(&[0x00, *note, *index]),
+            // the flashing color type takes the second color first.
+            LightMode::Flash { a, b } => payload.extend_from_slice(&[0x01, *note, *b, *a]),
+            LightMode::Pulse(index) => payload.extend_from_slice(&[0x02, *note, *index]),
+            LightMode::Rgb => payload.extend_from_slice(&[0x03, *note, color.red, color.green, color.blue])
+        }
+    }
+
+    sysex(device, &payload)
+}
+
+/// Finds the grid position of `note` in a layout of notes, indexed `[y][x]`.
+pub(crate) fn find_in_layout<const W: usize>(layout: &[[u8; W]], note: u8) -> Option<(usize, usize)> {
+    for (y, row) in layout.iter().enumerate() {
+        for (x, pad) in row.iter().enumerate() {
+            if *pad == note {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+/// Looks up the note of the pad at `(x, y)` in a layout of notes.
+pub(crate) fn layout_note<const W: usize>(layout: &[[u8; W]], x: usize, y: usize) -> Result<u8, LaunchpadError> {
+    layout.get(y)
+        .and_then(|row| row.get(x))
+        .copied()
+        .filter(|note| *note != NO_PAD)
+        .ok_or(LaunchpadError::InvalidCoordinate { x, y })
+}
+
+/// Converts `(x, y, color)` lights to `(note, color)` pairs, failing on the
+/// first coordinate outside the layout.
+pub(crate) fn layout_lights<const W: usize>(
+    layout: &[[u8; W]],
+    lights: &[(usize, usize, LaunchpadColor)]
+) -> Result<Vec<(u8, LaunchpadColor)>, LaunchpadError> {
+    lights.iter()
+        .map(|(x, y, color)| Ok((layout_note(layout, *x, *y)?, *color)))
+        .collect()
+}
+
+/// `color` corrected by `profile`, if there is one.
+pub(crate) fn calibrate(profile: Option<&ColorProfile>, color: LaunchpadColor) -> LaunchpadColor {
+    profile.map_or(color, |profile| profile.apply(color))
+}
+
+/// Like `layout_lights`, for lights with a lighting mode. Palette indices
+/// are checked here, as `mode_lights` cannot send ones above 127.
+pub(crate) fn layout_mode_lights<const W: usize>(
+    layout: &[[u8; W]],
+    lights: &[(usize, usize, LaunchpadColor, LightMode)]
+) -> Result<Vec<(u8, LaunchpadColor, LightMode)>, LaunchpadError> {
+    lights.iter()
+        .map(|(x, y, color, mode)| {
+            mode.validate()?;
+            Ok((layout_note(layout, *x, *y)?, *color, *mode))
+        })
+        .collect()
+}
+
+/// Parses note, control change and pressure messages from a device in
+/// Programmer mode, naming the pads after `buttons`.
+pub(crate) fn parse_message<const W: usize>(
+    layout: &[[u8; W]],
+    buttons: ButtonLayout,
+    message: &[u8]
+) -> Option<LaunchpadEventArgs> {
+    match *message {
+        [0x80, note, _] => {
+            find_in_layout(layout, note)
+                .map(|(x, y)| LaunchpadEventArgs::Released { x, y, button: buttons.button_at(x, y) })
+        }
+        [0x90 | 0xB0, note, velocity] => {
+            find_in_layout(layout, note).map(|(x, y)| {
+                let button = buttons.button_at(x, y);
+                match velocity {
+                    0 => LaunchpadEventArgs::Released { x, y, button },
+                    _ => LaunchpadEventArgs::Pressed { x, y, button, velocity }
+                }
+            })
+        }
+        [0xA0, note, pressure] => {
+            find_in_layout(layout, note)
+                .map(|(x, y)| LaunchpadEventArgs::Aftertouch { x, y, button: buttons.button_at(x, y), pressure })
+        }
+        [0xD0, pressure] => Some(LaunchpadEventArgs::ChannelPressure { pressure }),
+        _ => None
+    }
+}
+
+/// Forwards messages from `transport` to `event`, using `parse` to decode pad
+/// messages and passing on the rest as `Raw`, and keeps the latest Device
+/// Inquiry reply in `info`.
+pub(crate) fn listen(
+    transport: &mut dyn MidiTransport,
+    event: Arc<Mutex<LaunchpadEvent>>,
+    info: Arc<Mutex<Option<DeviceInfo>>>,
+    parse: fn(&[u8]) -> Option<LaunchpadEventArgs>
+) {
+    transport.listen(Box::new(move |stamp, message| {
+        if let Some(reply) = DeviceInfo::parse(message) {
+            *info.lock().unwrap() = Some(reply);
+        }
+
+        let args = parse(message).unwrap_or_else(|| LaunchpadEventArgs::Raw(message.to_vec()));
+        event.lock().unwrap().trigger_at(args, stamp);
+    }));
+}
@@ -0,0 +1,206 @@
+use regex::Regex;
+
+use crate::error::LaunchpadError;
+use crate::launchpad_x::LaunchpadX;
+use crate::launchpad_mini_mk3::LaunchpadMiniMk3;
+use crate::launchpad_pro_mk3::LaunchpadProMk3;
+use crate::launchpad_mk2::LaunchpadMk2;
+use crate::launchpad_s::LaunchpadS;
+
+/// Callback given the available port names, returning the index of the port to use.
+pub type PortCallback = Box<dyn Fn(&[String]) -> Option<usize> + Send + Sync>;
+
+/// Chooses which MIDI port to open from the names of the available ports.
+///
+/// Selection never blocks or prompts: if no port fits, a
+/// `LaunchpadError::PortNotFound` is returned instead.
+pub enum PortSelector {
+    /// The port with exactly this name.
+    Name(String),
+    /// The first port whose name matches this pattern.
+    Pattern(Regex),
+    /// The port at this position in the port list.
+    Index(usize),
+    /// The port named by this environment variable.
+    Env(String),
+    /// The result of the first selector that finds a port.
+    FirstOf(Vec<PortSelector>),
+    /// The port chosen by a callback.
+    Callback(PortCallback)
+}
+
+impl PortSelector {
+    /// Environment variable consulted by the default selector.
+    pub const ENV_VAR: &'static str = "LAUNCHPAD_PORT";
+
+    pub fn select(&self, names: &[String]) -> Result<usize, LaunchpadError> {
+        if names.is_empty() {
+            return Err(LaunchpadError::NoPorts);
+        }
+
+        match self {
+            PortSelector::Name(name) => {
+                names.iter().position(|n| n == name)
+                    .ok_or_else(|| LaunchpadError::PortNotFound(format!("no port named \"{}\"", name)))
+            }
+            PortSelector::Pattern(re) => {
+                names.iter().position(|n| re.is_match(n))
+                    .ok_or_else(|| LaunchpadError::PortNotFound(format!("no port matching \"{}\"", re)))
+            }
+            PortSelector::Index(index) => {
+                if *index < names.len() {
+                    Ok(*index)
+                } else {
+                    Err(LaunchpadError::PortNotFound(format!("no port at index {}", index)))
+                }
+            }
+            PortSelector::Env(var) => {
+                match std::env::var(var) {
+                    Ok(name) => PortSelector::Name(name).select(names),
+                    Err(_) => Err(LaunchpadError::PortNotFound(format!("{} is not set", var)))
+                }
+            }
+            PortSelector::FirstOf(selectors) => {
+                let mut last_err = LaunchpadError::PortNotFound("no selectors given".to_string());
+                for selector in selectors.iter() {
+                    match selector.select(names) {
+                        Ok(index) => return Ok(index),
+                        Err(err) => last_err = err
+                    }
+                }
+                Err(last_err)
+            }
+            PortSelector::Callback(callback) => {
+                callback(names)
+                    .filter(|index| *index < names.len())
+                    .ok_or_else(|| LaunchpadError::PortNotFound("no port chosen by callback".to_string()))
+            }
+        }
+    }
+
+    /// Picks an output port, then the input port of the same device. If that
+    /// input cannot be found by name, the input is picked like the output.
+    pub fn select_pair(&self, inputs: &[String], outputs: &[String]) -> Result<(usize, usize), LaunchpadError> {
+        let output = self.select(outputs)?;
+        let input = match PortSelector::input_for(&outputs[output]).select(inputs) {
+            Ok(input) => input,
+            Err(_) => self.select(inputs)?
+        };
+        Ok((input, output))
+    }
+
+    /// The input port of the device whose output port is named `output`.
+    ///
+    /// Most systems give both ports the same name, but Windows names them
+    /// apart, as in `MIDIIN2 (LPX MIDI)` and `MIDIOUT2 (LPX MIDI)`.
+    pub fn input_for(output: &str) -> PortSelector {
+        let mut selectors = vec![PortSelector::Name(output.to_string())];
+        if let Some(device) = output.strip_prefix("MIDIOUT") {
+            selectors.push(PortSelector::Name(format!("MIDIIN{}", device)));
+        }
+        PortSelector::FirstOf(selectors)
+    }
+
+    /// Picks the selected port of a midir input or output, along with its name.
+    pub fn select_port<T: midir::MidiIO>(&self, midi: &T) -> Result<(T::Port, String), LaunchpadError> {
+        let ports = midi.ports();
+
+        let mut names = Vec::with_capacity(ports.len());
+        for port in ports.iter() {
+            names.push(midi.port_name(port)?);
+        }
+
+        let index = self.select(&names)?;
+
+        Ok((ports[index].clone(), names.swap_remove(index)))
+    }
+
+    /// The first port that looks like a supported Launchpad.
+    pub(crate) fn launchpad_ports() -> PortSelector {
+        PortSelector::FirstOf(vec![
+            PortSelector::Pattern(Regex::new(LaunchpadX::PORT_PATTERN).unwrap()),
+            PortSelector::Pattern(Regex::new(LaunchpadMiniMk3::PORT_PATTERN).unwrap()),
+            PortSelector::Pattern(Regex::new(LaunchpadProMk3::PORT_PATTERN).unwrap()),
+            PortSelector::Pattern(Regex::new(LaunchpadMk2::PORT_PATTERN).unwrap()),
+            PortSelector::Pattern(Regex::new(LaunchpadS::PORT_PATTERN).unwrap())
+        ])
+    }
+
+    /// The default selector, reading the port name from `var`.
+    fn default_with_env(var: &str) -> PortSelector {
+        PortSelector::FirstOf(vec![
+            PortSelector::Env(var.to_string()),
+            PortSelector::launchpad_ports(),
+            PortSelector::Callback(Box::new(|names| if names.len() == 1 { Some(0) } else { None }))
+        ])
+    }
+}
+
+impl Default for PortSelector {
+    /// The port named by `LAUNCHPAD_PORT` if it is set, otherwise the first
+    /// port that looks like a supported Launchpad, or the only port there is.
+    fn default() -> PortSelector {
+        PortSelector::default_with_env(PortSelector::ENV_VAR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec![
+            "Midi Through:Midi Through Port-0 14:0".to_string(),
+            "Launchpad X:Launchpad X MIDI 1 20:0".to_string(),
+            "Launchpad X:Launchpad X MIDI 2 20:1".to_string()
+        ]
+    }
+
+    #[test]
+    fn selects_by_name_pattern_and_index() {
+        assert_eq!(PortSelector::Name("Launchpad X:Launchpad X MIDI 1 20:0".to_string()).select(&names()).unwrap(), 1);
+        assert_eq!(PortSelector::Pattern(Regex::new("MIDI 2").unwrap()).select(&names()).unwrap(), 2);
+        assert_eq!(PortSelector::Index(0).select(&names()).unwrap(), 0);
+        assert_eq!(PortSelector::Callback(Box::new(|names| names.iter().position(|n| n.contains("MIDI 1")))).select(&names()).unwrap(), 1);
+    }
+
+    #[test]
+    fn default_prefers_launchpads_then_the_only_port() {
+        let selector = PortSelector::default_with_env("LAUNCHPAD_SELECTOR_TEST_UNSET");
+        assert_eq!(selector.select(&names()).unwrap(), 2);
+        assert_eq!(selector.select(&["Some Synth".to_string()]).unwrap(), 0);
+        assert!(matches!(selector.select(&names()[..2]), Err(LaunchpadError::PortNotFound(_))));
+    }
+
+    #[test]
+    fn pairs_inputs_with_differently_named_outputs() {
+        let inputs = vec!["LPX MIDI".to_string(), "MIDIIN2 (LPX MIDI)".to_string()];
+        let outputs = vec!["LPX MIDI".to_string(), "MIDIOUT2 (LPX MIDI)".to_string()];
+
+        assert_eq!(PortSelector::Name("MIDIOUT2 (LPX MIDI)".to_string()).select_pair(&inputs, &outputs).unwrap(), (1, 1));
+        assert_eq!(PortSelector::Name("LPX MIDI".to_string()).select_pair(&inputs, &outputs).unwrap(), (0, 0));
+        let other_inputs = vec!["Some Synth".to_string(), "LPX MIDI In".to_string()];
+        assert_eq!(PortSelector::Pattern(Regex::new("LPX MIDI").unwrap()).select_pair(&other_inputs, &outputs[1..]).unwrap(), (1, 0));
+        assert_eq!(PortSelector::Index(0).select_pair(&names(), &names()).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn fails_instead_of_prompting() {
+        assert!(matches!(PortSelector::Name("Launchpad Pro".to_string()).select(&names()), Err(LaunchpadError::PortNotFound(_))));
+        assert!(matches!(PortSelector::Index(3).select(&names()), Err(LaunchpadError::PortNotFound(_))));
+        assert!(matches!(PortSelector::Callback(Box::new(|_| Some(7))).select(&names()), Err(LaunchpadError::PortNotFound(_))));
+        assert!(matches!(PortSelector::Index(0).select(&[]), Err(LaunchpadError::NoPorts)));
+    }
+
+    #[test]
+    fn reads_name_from_environment() {
+        let selector = PortSelector::FirstOf(vec![
+            PortSelector::Env("LAUNCHPAD_SELECTOR_TEST_PORT".to_string()),
+            PortSelector::Index(0)
+        ]);
+        assert_eq!(selector.select(&names()).unwrap(), 0);
+
+        std::env::set_var("LAUNCHPAD_SELECTOR_TEST_PORT", "Launchpad X:Launchpad X MIDI 1 20:0");
+        assert_eq!(selector.select(&names()).unwrap(), 1);
+    }
+}
@@ -1,50 +1,104 @@
 use crate::LaunchpadOutput;
-use crate::color::LaunchpadColor;
+use crate::color::{LaunchpadColor, LightMode};
+use crate::error::LaunchpadError;
 
+#[derive(Clone)]
 pub struct LaunchpadState {
-    lights: [[LaunchpadColor;9];9]
+    width: usize,
+    height: usize,
+    lights: Vec<LaunchpadColor>,
+    modes: Vec<LightMode>
 }
 
 impl LaunchpadState {
+    /// Creates a dark 9x9 state, the size of the Launchpad X and Mini MK3.
     pub fn new() -> LaunchpadState {
-        LaunchpadState { lights: [[LaunchpadColor::BLACK;9];9] }
+        LaunchpadState::with_size(9, 9)
     }
 
-    pub fn get_lights(&self) -> &[[LaunchpadColor;9];9] {
-        &self.lights
+    pub fn with_size(width: usize, height: usize) -> LaunchpadState {
+        LaunchpadState {
+            width,
+            height,
+            lights: vec![LaunchpadColor::BLACK; width * height],
+            modes: vec![LightMode::Rgb; width * height]
+        }
     }
-}
 
-impl LaunchpadOutput for LaunchpadState {
-    fn set_all_lights(&mut self, color: LaunchpadColor) {
-        for y in 0..9 {
-            for x in 0..9 {
-                self.lights[y][x] = color;
-            }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_light(&self, x: usize, y: usize) -> Option<LaunchpadColor> {
+        if x < self.width && y < self.height {
+            Some(self.lights[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mode(&self, x: usize, y: usize) -> Option<LightMode> {
+        if x < self.width && y < self.height {
+            Some(self.modes[y * self.width + x])
+        } else {
+            None
         }
     }
 
-    fn set_light(&mut self, x: usize, y: usize, color: LaunchpadColor) {
-        self.lights[y][x] = color;
+    /// Iterates over the rows of lights, top row first.
+    pub fn rows(&self) -> std::slice::Chunks<'_, LaunchpadColor> {
+        self.lights.chunks(self.width.max(1))
     }
 
-    fn set_state(&mut self, state: LaunchpadState) {
-        for y in 0..9 {
-            for x in 0..9 {
-                self.lights[y][x] = state.get_lights()[y][x];
-            }
+    fn check_bounds(&self, x: usize, y: usize) -> Result<(), LaunchpadError> {
+        if x < self.width && y < self.height {
+            Ok(())
+        } else {
+            Err(LaunchpadError::InvalidCoordinate { x, y })
         }
     }
+}
 
-    fn clear_grid(&mut self) {
-        self.set_all_lights(LaunchpadColor::BLACK);
+impl Default for LaunchpadState {
+    fn default() -> LaunchpadState {
+        LaunchpadState::new()
     }
+}
 
-    fn set_box(&mut self, x: usize, y: usize, width: usize, height: usize, color: LaunchpadColor) {
-        for y in y..y+height {
-            for x in x..x+width {
-                self.lights[y][x] = color;
-            }
+impl LaunchpadOutput for LaunchpadState {
+    fn grid_size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn has_light_modes(&self) -> bool {
+        true
+    }
+
+    /// Sets the lights' colors, showing them as RGB.
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        for (x, y, _) in lights.iter() {
+            self.check_bounds(*x, *y)?;
+        }
+        for (x, y, color) in lights.iter() {
+            self.lights[*y * self.width + *x] = *color;
+            self.modes[*y * self.width + *x] = LightMode::Rgb;
         }
+        Ok(())
     }
-}
\ No newline at end of file
+
+    fn set_lights_with_mode(&mut self, lights: &[(usize, usize, LaunchpadColor, LightMode)]) -> Result<(), LaunchpadError> {
+        for (x, y, _, mode) in lights.iter() {
+            self.check_bounds(*x, *y)?;
+            mode.validate()?;
+        }
+        for (x, y, color, mode) in lights.iter() {
+            self.lights[*y * self.width + *x] = *color;
+            self.modes[*y * self.width + *x] = *mode;
+        }
+        Ok(())
+    }
+}
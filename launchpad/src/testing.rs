@@ -0,0 +1,30 @@
+//! Helpers shared by the unit tests of several modules.
+
+use std::sync::{Arc, Mutex};
+
+use crate::error::LaunchpadError;
+use crate::event::{LaunchpadEventArgs, LaunchpadEventHandler};
+use crate::transport::{LoopbackTransport, MidiTransport};
+
+/// Keeps every event it is notified of.
+pub(crate) struct Recorder {
+    pub events: Arc<Mutex<Vec<LaunchpadEventArgs>>>
+}
+
+impl LaunchpadEventHandler for Recorder {
+    fn notify(&self, args: &LaunchpadEventArgs) {
+        self.events.lock().unwrap().push(args.clone());
+    }
+}
+
+/// Opens a backend on a new loopback transport, forgetting the messages it
+/// sent while opening.
+pub(crate) fn open<T>(
+    open: impl FnOnce(Box<dyn MidiTransport>) -> Result<T, LaunchpadError>
+) -> (T, LoopbackTransport) {
+    let transport = LoopbackTransport::new();
+    let launchpad = open(Box::new(transport.clone())).unwrap();
+    transport.take_sent();
+
+    (launchpad, transport)
+}
@@ -0,0 +1,154 @@
+use std::sync::{Arc, Mutex};
+
+use crate::error::LaunchpadError;
+use crate::selector::PortSelector;
+
+/// Callback invoked with the timestamp and bytes of every incoming MIDI message.
+pub type MidiCallback = Box<dyn FnMut(u64, &[u8]) + Send>;
+
+/// A connection that can send bytes to a device and deliver the bytes it sends back.
+pub trait MidiTransport: Send {
+    fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError>;
+    fn listen(&mut self, callback: MidiCallback);
+}
+
+/// Transport backed by a pair of midir connections to a physical device.
+pub struct MidirTransport {
+    input: midir::MidiInputConnection<()>,
+    output: midir::MidiOutputConnection,
+    port_name: String,
+
+    callback: Arc<Mutex<Option<MidiCallback>>>
+}
+
+impl MidirTransport {
+    /// Connects to the output port chosen by `selector` and the input port of
+    /// the same device, which may be named differently.
+    pub fn connect(selector: &PortSelector) -> Result<MidirTransport, LaunchpadError> {
+        let callback: Arc<Mutex<Option<MidiCallback>>> = Arc::new(Mutex::new(None));
+
+        let (input, output) = selector.select_pair(&MidirTransport::input_port_names()?, &MidirTransport::port_names()?)?;
+
+        let input = MidirTransport::init_input_device(&PortSelector::Index(input), callback.clone())?;
+        let (output, port_name) = MidirTransport::init_output_device(&PortSelector::Index(output))?;
+
+        Ok(MidirTransport { input, output, port_name, callback })
+    }
+
+    /// Names of every MIDI output port, in the order midir lists them.
+    pub fn port_names() -> Result<Vec<String>, LaunchpadError> {
+        let midi_out = midir::MidiOutput::new("Launchpad Output")?;
+
+        let mut names = Vec::new();
+        for port in midi_out.ports().iter() {
+            names.push(midi_out.port_name(port)?);
+        }
+        Ok(names)
+    }
+
+    /// Names of every MIDI input port, in the order midir lists them.
+    pub fn input_port_names() -> Result<Vec<String>, LaunchpadError> {
+        let midi_in = midir::MidiInput::new("Launchpad Output")?;
+
+        let mut names = Vec::new();
+        for port in midi_in.ports().iter() {
+            names.push(midi_in.port_name(port)?);
+        }
+        Ok(names)
+    }
+
+    /// Name of the output port this transport is connected to.
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn init_input_device(
+        selector: &PortSelector,
+        callback: Arc<Mutex<Option<MidiCallback>>>
+    ) -> Result<midir::MidiInputConnection<()>, LaunchpadError> {
+        let mut midi_in = midir::MidiInput::new("Launchpad Output")?;
+        midi_in.ignore(midir::Ignore::None); // SysEx replies are needed to identify the device.
+
+        let (in_port, _) = selector.select_port(&midi_in)?;
+
+        let conn_in = midi_in.connect(&in_port, "launchpad-api", move |stamp, message, _| {
+            if let Some(callback) = callback.lock().unwrap().as_mut() {
+                callback(stamp, message);
+            }
+        }, ())?;
+
+        Ok(conn_in)
+    }
+
+    pub fn init_output_device(selector: &PortSelector) -> Result<(midir::MidiOutputConnection, String), LaunchpadError> {
+        let midi_out = midir::MidiOutput::new("Launchpad Output")?;
+
+        let (out_port, port_name) = selector.select_port(&midi_out)?;
+
+        let conn_out = midi_out.connect(&out_port, "launchpad-api")?;
+
+        Ok((conn_out, port_name))
+    }
+}
+
+impl MidiTransport for MidirTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+        self.output.send(message)?;
+        Ok(())
+    }
+
+    fn listen(&mut self, callback: MidiCallback) {
+        *self.callback.lock().unwrap() = Some(callback);
+    }
+}
+
+/// In-memory transport that records everything sent to it and lets the caller
+/// inject incoming messages, so a backend can be driven without hardware.
+///
+/// Clones share the same buffers: hand one clone to the backend and keep
+/// another to inspect or drive it.
+#[derive(Clone, Default)]
+pub struct LoopbackTransport {
+    sent: Arc<Mutex<Vec<Vec<u8>>>>,
+    callback: Arc<Mutex<Option<MidiCallback>>>
+}
+
+impl LoopbackTransport {
+    pub fn new() -> LoopbackTransport {
+        LoopbackTransport::default()
+    }
+
+    /// Delivers `message` to the listener as if it had come from the device.
+    pub fn receive(&self, message: &[u8]) {
+        self.receive_at(0, message);
+    }
+
+    /// Delivers `message` to the listener as if it had arrived at `stamp`,
+    /// in microseconds.
+    pub fn receive_at(&self, stamp: u64, message: &[u8]) {
+        if let Some(callback) = self.callback.lock().unwrap().as_mut() {
+            callback(stamp, message);
+        }
+    }
+
+    /// Returns every message sent so far, oldest first.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// Returns and forgets every message sent so far.
+    pub fn take_sent(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+impl MidiTransport for LoopbackTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), LaunchpadError> {
+        self.sent.lock().unwrap().push(message.to_vec());
+        Ok(())
+    }
+
+    fn listen(&mut self, callback: MidiCallback) {
+        *self.callback.lock().unwrap() = Some(callback);
+    }
+}
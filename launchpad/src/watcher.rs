@@ -0,0 +1,432 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{Launchpad, LaunchpadOutput, LaunchpadColor, LaunchpadState};
+use crate::color::{ColorProfile, LightMode};
+use crate::button::ButtonLayout;
+use crate::device::{DeviceInfo, LaunchpadDescriptor, LaunchpadModel};
+use crate::error::LaunchpadError;
+use crate::event::*;
+use crate::selector::PortSelector;
+use crate::transport::MidirTransport;
+
+/// Lists ports and opens devices for a `WatchedLaunchpad`.
+pub trait DeviceConnector: Send {
+    fn port_names(&self) -> Result<Vec<String>, LaunchpadError>;
+    fn connect(&self, port_name: &str) -> Result<Box<dyn Launchpad>, LaunchpadError>;
+}
+
+/// Connects to real devices of one model through midir.
+pub struct MidirConnector {
+    model: LaunchpadModel
+}
+
+impl MidirConnector {
+    pub fn new(model: LaunchpadModel) -> MidirConnector {
+        MidirConnector { model }
+    }
+}
+
+impl DeviceConnector for MidirConnector {
+    fn port_names(&self) -> Result<Vec<String>, LaunchpadError> {
+        MidirTransport::port_names()
+    }
+
+    fn connect(&self, port_name: &str) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+        let transport = MidirTransport::connect(&PortSelector::Name(port_name.to_string()))?;
+
+        self.model.open(Box::new(transport), None)
+    }
+}
+
+/// The current device, locked apart from `Shared` so that a query waiting
+/// for the device's reply does not hold up the rest of the launchpad.
+type Device = Arc<Mutex<Box<dyn Launchpad>>>;
+
+struct Shared {
+    device: Option<Device>,
+    state: LaunchpadState,
+    buttons: ButtonLayout,
+    light_modes: bool,
+    profile: Option<ColorProfile>,
+    brightness: Option<u8>,
+    sleeping: bool
+}
+
+/// A `Launchpad` that survives being unplugged.
+///
+/// A background thread polls the port list. When the port goes away a
+/// `Disconnected` event is triggered; when it comes back the device is
+/// reopened, which puts it back into Programmer mode, the last state set is
+/// sent again, and a `Connected` event is triggered. Pad events from whichever
+/// device is currently open are passed on through this handle's own event.
+pub struct WatchedLaunchpad {
+    shared: Arc<Mutex<Shared>>,
+    event: Arc<Mutex<LaunchpadEvent>>,
+
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>
+}
+
+impl WatchedLaunchpad {
+    pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// Opens a Launchpad found by `list_launchpads` and starts watching its port.
+    pub fn open(descriptor: &LaunchpadDescriptor) -> Result<WatchedLaunchpad, LaunchpadError> {
+        let connector = MidirConnector::new(descriptor.model());
+
+        WatchedLaunchpad::with_connector(&descriptor.port_name, Box::new(connector), WatchedLaunchpad::POLL_INTERVAL)
+    }
+
+    pub fn with_connector(
+        port_name: &str,
+        connector: Box<dyn DeviceConnector>,
+        interval: Duration
+    ) -> Result<WatchedLaunchpad, LaunchpadError> {
+        let event = Arc::new(Mutex::new(LaunchpadEvent::default()));
+
+        let device = connector.connect(port_name)?;
+        device.set_event_handler(Box::new(Forwarder { event: event.clone() }));
+
+        let (width, height) = device.grid_size();
+        let buttons = device.button_layout();
+        let light_modes = device.has_light_modes();
+        let shared = Arc::new(Mutex::new(Shared {
+            device: Some(Arc::new(Mutex::new(device))),
+            state: LaunchpadState::with_size(width, height),
+            buttons,
+            light_modes,
+            profile: None,
+            brightness: None,
+            sleeping: false
+        }));
+
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let watch = Watch {
+            port_name: port_name.to_string(),
+            connector,
+            shared: shared.clone(),
+            event: event.clone(),
+            stop: stop.clone()
+        };
+        let thread = thread::spawn(move || watch.run(interval));
+
+        Ok(WatchedLaunchpad { shared, event, stop, thread: Some(thread) })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.lock().unwrap().device.is_some()
+    }
+
+    /// The current device, if it is connected.
+    fn device(&self) -> Result<Device, LaunchpadError> {
+        self.shared.lock().unwrap().device.clone()
+            .ok_or_else(|| LaunchpadError::SendFailed("device is disconnected".to_string()))
+    }
+}
+
+impl Drop for WatchedLaunchpad {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl LaunchpadOutput for WatchedLaunchpad {
+    fn grid_size(&self) -> (usize, usize) {
+        self.shared.lock().unwrap().state.grid_size()
+    }
+
+    fn button_layout(&self) -> ButtonLayout {
+        self.shared.lock().unwrap().buttons
+    }
+
+    fn has_light_modes(&self) -> bool {
+        self.shared.lock().unwrap().light_modes
+    }
+
+    /// Remembers the lights even while disconnected, so they can be restored,
+    /// but reports an error if they did not reach a device.
+    fn set_lights(&mut self, lights: &[(usize, usize, LaunchpadColor)]) -> Result<(), LaunchpadError> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.state.set_lights(lights)?;
+
+        match shared.device.as_ref() {
+            Some(device) => device.lock().unwrap().set_lights(lights),
+            None => Err(LaunchpadError::SendFailed("device is disconnected".to_string()))
+        }
+    }
+
+    /// Kept for the device reopened after it is plugged back in.
+    fn set_color_profile(&mut self, profile: Option<ColorProfile>) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.profile = profile;
+
+        if let Some(device) = shared.device.as_ref() {
+            device.lock().unwrap().set_color_profile(profile);
+        }
+    }
+
+    /// Kept for the device reopened after it is plugged back in.
+    fn set_brightness(&mut self, level: u8) -> Result<(), LaunchpadError> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.brightness = Some(level);
+
+        match shared.device.as_ref() {
+            Some(device) => device.lock().unwrap().set_brightness(level),
+            None => Err(LaunchpadError::SendFailed("device is disconnected".to_string()))
+        }
+    }
+
+    fn brightness(&mut self) -> Result<u8, LaunchpadError> {
+        self.device()?.lock().unwrap().brightness()
+    }
+
+    /// Kept for the device reopened after it is plugged back in.
+    fn set_sleeping(&mut self, sleeping: bool) -> Result<(), LaunchpadError> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.sleeping = sleeping;
+
+        match shared.device.as_ref() {
+            Some(device) => device.lock().unwrap().set_sleeping(sleeping),
+            None => Err(LaunchpadError::SendFailed("device is disconnected".to_string()))
+        }
+    }
+
+    fn is_sleeping(&mut self) -> Result<bool, LaunchpadError> {
+        self.device()?.lock().unwrap().is_sleeping()
+    }
+
+    /// Remembers the modes as given, so a device without lighting modes shows
+    /// the same static colors again when it is restored.
+    fn set_lights_with_mode(&mut self, lights: &[(usize, usize, LaunchpadColor, LightMode)]) -> Result<(), LaunchpadError> {
+        let mut shared = self.shared.lock().unwrap();
+        shared.state.set_lights_with_mode(lights)?;
+
+        match shared.device.as_ref() {
+            Some(device) => device.lock().unwrap().set_lights_with_mode(lights),
+            None => Err(LaunchpadError::SendFailed("device is disconnected".to_string()))
+        }
+    }
+}
+
+impl Launchpad for WatchedLaunchpad {
+    fn get_event(&self) -> &Arc<Mutex<LaunchpadEvent>> {
+        &self.event
+    }
+
+    fn set_event_handler(&self, handler: Box<dyn LaunchpadEventHandler>) {
+        self.event.lock().unwrap().subscribe(handler);
+    }
+
+    fn device_info(&self) -> Option<DeviceInfo> {
+        self.shared.lock().unwrap().device.as_ref().and_then(|device| device.lock().unwrap().device_info())
+    }
+}
+
+/// Passes events from the current device on to the watched handle.
+struct Forwarder {
+    event: Arc<Mutex<LaunchpadEvent>>
+}
+
+impl LaunchpadEventHandler for Forwarder {
+    fn notify(&self, args: &LaunchpadEventArgs) {
+        self.event.lock().unwrap().trigger(args.clone());
+    }
+
+    fn notify_at(&self, args: &LaunchpadEventArgs, time: EventTime) {
+        self.event.lock().unwrap().trigger_timed(args.clone(), time);
+    }
+}
+
+/// State owned by the polling thread.
+struct Watch {
+    port_name: String,
+    connector: Box<dyn DeviceConnector>,
+    shared: Arc<Mutex<Shared>>,
+    event: Arc<Mutex<LaunchpadEvent>>,
+    stop: Arc<AtomicBool>
+}
+
+impl Watch {
+    fn run(self, interval: Duration) {
+        while !self.stop.load(Ordering::SeqCst) {
+            thread::sleep(interval);
+
+            let present = match self.connector.port_names() {
+                Ok(names) => names.contains(&self.port_name),
+                Err(_) => continue
+            };
+
+            // events are triggered without holding the lock, as handlers
+            // usually set lights through this same launchpad.
+            if let Some(args) = self.poll(present) {
+                self.event.lock().unwrap().trigger(args);
+            }
+        }
+    }
+
+    fn poll(&self, present: bool) -> Option<LaunchpadEventArgs> {
+        let mut shared = self.shared.lock().unwrap();
+
+        match (shared.device.is_some(), present) {
+            (true, false) => {
+                // closing the device waits for its input thread, which may be
+                // in a handler waiting for this lock, so it is dropped after.
+                let device = shared.device.take();
+                drop(shared);
+                drop(device);
+                Some(LaunchpadEventArgs::Disconnected)
+            }
+            (false, true) => {
+                let mut device = self.connector.connect(&self.port_name).ok()?;
+                device.set_event_handler(Box::new(Forwarder { event: self.event.clone() }));
+                device.set_color_profile(shared.profile);
+
+                // restoring is best effort: the device is kept even if the
+                // lights or settings could not be sent, rather than being
+                // reopened and failing the same way on every poll.
+                let _ = device.set_state(shared.state.clone());
+                if let Some(level) = shared.brightness {
+                    let _ = device.set_brightness(level);
+                }
+                if shared.sleeping {
+                    let _ = device.sleep();
+                }
+
+                shared.device = Some(Arc::new(Mutex::new(device)));
+                Some(LaunchpadEventArgs::Connected)
+            }
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Recorder;
+    use crate::button::Button;
+    use crate::transport::LoopbackTransport;
+
+    use std::time::Instant;
+
+    #[derive(Clone)]
+    struct FakeConnector {
+        model: LaunchpadModel,
+        ports: Arc<Mutex<Vec<String>>>,
+        transports: Arc<Mutex<Vec<LoopbackTransport>>>
+    }
+
+    impl FakeConnector {
+        fn new(model: LaunchpadModel) -> FakeConnector {
+            FakeConnector { model, ports: Arc::default(), transports: Arc::default() }
+        }
+    }
+
+    impl DeviceConnector for FakeConnector {
+        fn port_names(&self) -> Result<Vec<String>, LaunchpadError> {
+            Ok(self.ports.lock().unwrap().clone())
+        }
+
+        fn connect(&self, _port_name: &str) -> Result<Box<dyn Launchpad>, LaunchpadError> {
+            let transport = LoopbackTransport::new();
+            self.transports.lock().unwrap().push(transport.clone());
+            self.model.open(Box::new(transport), None)
+        }
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn reconnects_and_restores_state() {
+        let connector = FakeConnector::new(LaunchpadModel::X);
+        connector.ports.lock().unwrap().push("Launchpad X MIDI 2".to_string());
+
+        let mut launchpad = WatchedLaunchpad::with_connector("Launchpad X MIDI 2", Box::new(connector.clone()), Duration::from_millis(1)).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        launchpad.set_event_handler(Box::new(Recorder { events: events.clone() }));
+
+        launchpad.set_light(0, 8, LaunchpadColor::RED).unwrap();
+
+        connector.ports.lock().unwrap().clear();
+        wait_for(|| !launchpad.is_connected());
+        assert!(launchpad.set_light(1, 8, LaunchpadColor::GREEN).is_err());
+
+        connector.ports.lock().unwrap().push("Launchpad X MIDI 2".to_string());
+        wait_for(|| events.lock().unwrap().len() == 2);
+
+        let transports = connector.transports.lock().unwrap().clone();
+        assert_eq!(transports.len(), 2);
+
+        // the new device is put in Programmer mode, then sent both lights.
+        let sent = transports[1].sent();
+        assert_eq!(sent[0], vec![0xF0, 0x00, 0x20, 0x29, 0x02, 0x0C, 0x0E, 0x01, 0xF7]);
+        assert_eq!(&sent[2][7 + 72 * 5..7 + 74 * 5], &[0x03, 11, 127, 0, 0, 0x03, 12, 0, 127, 0]);
+
+        transports[1].receive(&[0x90, 11, 127]);
+        assert_eq!(*events.lock().unwrap(), vec![
+            LaunchpadEventArgs::Disconnected,
+            LaunchpadEventArgs::Connected,
+            LaunchpadEventArgs::Pressed { x: 0, y: 8, button: Button::Grid { x: 0, y: 7 }, velocity: 127 }
+        ]);
+    }
+
+    #[test]
+    fn restores_palette_modes_as_static_colors() {
+        let connector = FakeConnector::new(LaunchpadModel::Mk2);
+        connector.ports.lock().unwrap().push("Launchpad MK2".to_string());
+
+        let mut launchpad = WatchedLaunchpad::with_connector("Launchpad MK2", Box::new(connector.clone()), Duration::from_millis(1)).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        launchpad.set_event_handler(Box::new(Recorder { events: events.clone() }));
+
+        launchpad.set_light(0, 1, LaunchpadColor::RED).unwrap();
+        launchpad.set_light_with_mode(1, 1, LaunchpadColor::BLACK, LightMode::Pulse(5)).unwrap();
+        assert_eq!(launchpad.shared.lock().unwrap().state.get_mode(1, 1), Some(LightMode::Pulse(5)));
+
+        connector.ports.lock().unwrap().clear();
+        wait_for(|| !launchpad.is_connected());
+        connector.ports.lock().unwrap().push("Launchpad MK2".to_string());
+        wait_for(|| events.lock().unwrap().len() == 2);
+
+        // the whole state is restored in one message.
+        let transports = connector.transports.lock().unwrap().clone();
+        let restored = transports[1].sent().last().unwrap().clone();
+        assert_eq!(restored.len(), 7 + 80 * 4 + 1);
+        assert!(restored.windows(8).any(|lights| lights == [81, 63, 0, 0, 82, 63, 0, 0]));
+        assert_eq!(*events.lock().unwrap(), vec![LaunchpadEventArgs::Disconnected, LaunchpadEventArgs::Connected]);
+    }
+
+    #[test]
+    fn polls_while_waiting_for_a_query() {
+        let connector = FakeConnector::new(LaunchpadModel::X);
+        connector.ports.lock().unwrap().push("Launchpad X MIDI 2".to_string());
+
+        let mut launchpad = WatchedLaunchpad::with_connector("Launchpad X MIDI 2", Box::new(connector.clone()), Duration::from_millis(1)).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        launchpad.set_event_handler(Box::new(Recorder { events: events.clone() }));
+
+        let transport = connector.transports.lock().unwrap()[0].clone();
+        transport.take_sent();
+        let query = thread::spawn(move || launchpad.brightness());
+        wait_for(|| !transport.sent().is_empty());
+
+        // the device never answers, but the unplug is noticed meanwhile.
+        connector.ports.lock().unwrap().clear();
+        wait_for(|| events.lock().unwrap().len() == 1);
+        assert!(!query.is_finished());
+        assert!(matches!(query.join().unwrap(), Err(LaunchpadError::Timeout(_))));
+    }
+}
@@ -4,44 +4,55 @@ pub use launchpad::state::LaunchpadState;
 pub use launchpad::event::*;
 
 pub use std::io::stdin;
-pub use std::sync::{Arc, Mutex};
-
-pub struct MyEventHandler {
-    launchpad: Arc<Mutex<Box<dyn Launchpad>>>
-}
-
-impl LaunchpadEventHandler for MyEventHandler {
-    fn notify(&self, args: &LaunchpadEventArgs) {
-        let mut launchpad = self.launchpad.lock().unwrap();
-
-        match args {
-            LaunchpadEventArgs::Pressed { x, y } => launchpad.set_light(*x, *y, LaunchpadColor::GREEN),
-            LaunchpadEventArgs::Released { x, y } => launchpad.set_light(*x, *y, LaunchpadColor::BLACK)
-        }
-    }
-}
+pub use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+pub use std::thread;
+pub use std::time::Duration;
 
 fn main() {
     match create_launchpad() {
-        Ok(launchpad) => {
-            let launchpad = Arc::new(Mutex::new(launchpad));
+        Ok(mut launchpad) => {
+            let events = launchpad.events();
 
-            {
-                let mut locked = launchpad.lock().unwrap();
-                locked.set_event_handler(Box::new(MyEventHandler { launchpad: launchpad.clone() }));
+            let mut state = LaunchpadState::new();
 
-                let mut state = LaunchpadState::new();
+            state.clear_grid().unwrap();
+            state.set_light(2, 2, LaunchpadColor::RED).unwrap();
+            state.set_box(0, 0, 8, 1, LaunchpadColor::BLUE).unwrap();
+            state.set_light(4, 0, LaunchpadColor::GREEN).unwrap();
 
-                state.clear_grid();
-                state.set_light(2, 2, LaunchpadColor::RED);
-                state.set_box(0, 0, 8, 1, LaunchpadColor::BLUE);
-                state.set_light(4, 0, LaunchpadColor::GREEN);
+            if let Err(err) = launchpad.set_state(state) {
+                println!("Error: {}", err);
+            }
 
-                locked.set_state(state);
+            // pressing enter ends the event loop below.
+            let (quit, quit_requested) = mpsc::channel();
+            thread::spawn(move || {
+                let mut input = String::new();
+                let _ = stdin().read_line(&mut input);
+                let _ = quit.send(());
+            });
+
+            // the event loop owns the launchpad, so no handler or lock is needed.
+            while quit_requested.try_recv() == Err(TryRecvError::Empty) {
+                let event = match events.recv_timeout(Duration::from_millis(50)) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break
+                };
+
+                let result = match event.args {
+                    LaunchpadEventArgs::Pressed { x, y, .. } => launchpad.set_light(x, y, LaunchpadColor::GREEN),
+                    LaunchpadEventArgs::Released { x, y, .. } => launchpad.set_light(x, y, LaunchpadColor::BLACK),
+                    _ => Ok(())
+                };
+
+                if let Err(err) = result {
+                    println!("Error: {}", err);
+                }
             }
 
-            let mut input = String::new();
-            stdin().read_line(&mut input);
+            // closes the device, returning it to Live mode.
+            drop(launchpad);
         },
         Err(err) => println!("Error: {}", err)
     };
@@ -1,7 +1,10 @@
 mod shortcuts;
 
 use launchpad::*;
+use launchpad::button::Button;
 use launchpad::event::*;
+use launchpad::error::LaunchpadError;
+use launchpad::watcher::WatchedLaunchpad;
 
 use std::io::stdin;
 use std::sync::{Arc, Mutex, MutexGuard};
@@ -16,6 +19,10 @@ lazy_static! {
     static ref ENIGO: Arc<Mutex<Enigo>> = Arc::new(Mutex::new(Enigo::new()));
 }
 
+/// The row of control buttons along the top, which switch between pages.
+/// Models name these buttons differently, so they are found by position.
+const TAB_ROW: usize = 0;
+
 struct Application {
     launchpad: Arc<Mutex<Box<dyn Launchpad>>>,
 
@@ -26,7 +33,15 @@ struct Application {
 
 impl Application {
     pub fn new() -> Arc<Mutex<Application>> {
-        let launchpad = Arc::new(Mutex::new(create_launchpad().unwrap()));
+        // watch the device, so the lights come back after the cable is unplugged.
+        let descriptor = list_launchpads().unwrap().into_iter().next().expect("No Launchpad found.");
+        let launchpad = WatchedLaunchpad::open(&descriptor).unwrap();
+
+        Application::with_launchpad(Box::new(launchpad))
+    }
+
+    pub fn with_launchpad(launchpad: Box<dyn Launchpad>) -> Arc<Mutex<Application>> {
+        let launchpad = Arc::new(Mutex::new(launchpad));
 
         let lp_clone = launchpad.clone();
 
@@ -42,46 +57,44 @@ impl Application {
         application
     }
 
-    fn render(&mut self) {
+    fn render(&mut self) -> Result<(), LaunchpadError> {
         let mut locked = self.launchpad.lock().unwrap();
 
-        Application::clear(&mut locked);
-        Application::render_tab_bar(&mut locked, self.current_page);
-        Application::render_hot_bar(&mut locked, &self.hotbar);
-        Application::render_page(&mut locked, &self.pages.pages[self.current_page]);
+        Application::clear(&mut locked)?;
+        Application::render_tab_bar(&mut locked, self.current_page)?;
+        Application::render_hot_bar(&mut locked, &self.hotbar)?;
+        Application::render_page(&mut locked, &self.pages.pages[self.current_page])
     }
 
-    fn clear(launchpad: &mut MutexGuard<Box<dyn Launchpad>>) {
-        launchpad.clear_grid();
+    fn clear(launchpad: &mut MutexGuard<Box<dyn Launchpad>>) -> Result<(), LaunchpadError> {
+        launchpad.clear_grid()
     }
 
-    fn render_tab_bar(launchpad: &mut MutexGuard<Box<dyn Launchpad>>, page: usize) {
-        launchpad.set_box(0, 0, 8, 1, color::LaunchpadColor::BLUE);
-        launchpad.set_light(page, 0, color::LaunchpadColor::RED);
+    fn render_tab_bar(launchpad: &mut MutexGuard<Box<dyn Launchpad>>, page: usize) -> Result<(), LaunchpadError> {
+        for x in 0..8 {
+            launchpad.set_light(x, TAB_ROW, color::LaunchpadColor::BLUE)?;
+        }
+        launchpad.set_light(page, TAB_ROW, color::LaunchpadColor::RED)
     }
 
-    fn render_hot_bar(launchpad: &mut MutexGuard<Box<dyn Launchpad>>, hotbar: &ShortcutHotbar) {
+    fn render_hot_bar(launchpad: &mut MutexGuard<Box<dyn Launchpad>>, hotbar: &ShortcutHotbar) -> Result<(), LaunchpadError> {
         for y in 0..8 {
-            match &hotbar.shortcuts[y] {
-                Some(shortcut) => {
-                    launchpad.set_light(8, y+1, shortcut.color);
-                },
-                _ => ()
+            if let Some(shortcut) = &hotbar.shortcuts[y] {
+                launchpad.set_button_light(Button::Scene(y), shortcut.color)?;
             }
         }
+        Ok(())
     }
 
-    fn render_page(launchpad: &mut MutexGuard<Box<dyn Launchpad>>, page: &ShortcutPage) {
+    fn render_page(launchpad: &mut MutexGuard<Box<dyn Launchpad>>, page: &ShortcutPage) -> Result<(), LaunchpadError> {
         for y in 0..8 {
             for x in 0..8 {
-                match &page.shortcuts[y][x] {
-                    Some(shortcut) => {
-                        launchpad.set_light(x, y+1, shortcut.color);
-                    }
-                    _ => ()
+                if let Some(shortcut) = &page.shortcuts[y][x] {
+                    launchpad.set_button_light(Button::Grid { x, y }, shortcut.color)?;
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -91,38 +104,40 @@ struct ApplicationEventHandler {
 
 impl LaunchpadEventHandler for ApplicationEventHandler {
     fn notify(&self, args: &LaunchpadEventArgs) {
-        match args {
-            LaunchpadEventArgs::Pressed { x, y } => {
-                if *y == 0 {
+        let (x, y, button) = match args {
+            LaunchpadEventArgs::Pressed { x, y, button, .. } => (*x, *y, *button),
+            _ => return
+        };
+
+        match button {
+            Button::Grid { x, y } => {
+                let locked = self.application.lock().unwrap();
+                if let Some(shortcut) = &locked.pages.pages[locked.current_page].shortcuts[y][x] {
+                    shortcut.invoke();
+                }
+            }
+            Button::Scene(row) => {
+                let locked = self.application.lock().unwrap();
+                if let Some(shortcut) = &locked.hotbar.shortcuts[row] {
+                    shortcut.invoke();
+                }
+            }
+            _ => {
+                if y == TAB_ROW && x < 8 {
                     let mut locked = self.application.lock().unwrap();
-                    locked.current_page = *x;
-                    locked.render();
-                } else if *x == 8 {
-                    let locked = self.application.lock().unwrap();
-                    match &locked.hotbar.shortcuts[*y-1] {
-                        Some(shortcut) => {
-                            shortcut.invoke();
-                        },
-                        None => ()
-                    }
-                } else {
-                    let locked = self.application.lock().unwrap();
-                    match &locked.pages.pages[locked.current_page].shortcuts[*x][*y-1] {
-                        Some(shortcut) => {
-                            shortcut.invoke();
-                        },
-                        None => ()
+                    locked.current_page = x;
+                    if let Err(err) = locked.render() {
+                        println!("Error: {}", err);
                     }
                 }
-            },
-            _ => ()
+            }
         }
     }
 }
 
 fn wait() {
     let mut input = String::new();
-    stdin().read_line(&mut input);
+    let _ = stdin().read_line(&mut input);
 }
 
 fn main() {
@@ -133,7 +148,7 @@ fn main() {
             callback: Box::new(|| {
                 ENIGO.lock().unwrap().key_sequence("hello world");
             }),
-            color: color::LaunchpadColor { red: 0, green: 87, blue: 107 }
+            color: color::LaunchpadColor::from_hex("#00afd7").unwrap()
         });
         locked.hotbar.shortcuts[7] = Some(Shortcut {
             callback: Box::new(|| {
@@ -141,7 +156,78 @@ fn main() {
             }),
             color: color::LaunchpadColor::RED
         });
-        locked.render();
+        locked.render().unwrap();
     }
     wait();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use launchpad::launchpad_x::LaunchpadX;
+    use launchpad::mock::MockLaunchpad;
+    use launchpad::transport::LoopbackTransport;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn loopback_application() -> (Arc<Mutex<Application>>, LoopbackTransport) {
+        let transport = LoopbackTransport::new();
+        let launchpad = LaunchpadX::with_transport(Box::new(transport.clone())).unwrap();
+
+        (Application::with_launchpad(Box::new(launchpad)), transport)
+    }
+
+    #[test]
+    fn tab_bar_switches_page() {
+        let (app, transport) = loopback_application();
+        transport.take_sent();
+
+        // top row, fourth pad.
+        transport.receive(&[0xB0, 94, 127]);
+
+        assert_eq!(app.lock().unwrap().current_page, 3);
+        assert!(!transport.take_sent().is_empty());
+    }
+
+    #[test]
+    fn hotbar_invokes_shortcut() {
+        let (app, transport) = loopback_application();
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        app.lock().unwrap().hotbar.shortcuts[0] = Some(Shortcut {
+            callback: Box::new(move || { counter.fetch_add(1, Ordering::SeqCst); }),
+            color: color::LaunchpadColor::GREEN
+        });
+
+        // right column, first row below the tab bar.
+        transport.receive(&[0x90, 89, 127]);
+        transport.receive(&[0x90, 89, 0]);
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn render_draws_tab_bar_and_shortcuts() {
+        let mock = MockLaunchpad::new();
+        let app = Application::with_launchpad(Box::new(mock.clone()));
+
+        {
+            let mut locked = app.lock().unwrap();
+            locked.pages.pages[0].shortcuts[2][5] = Some(Shortcut {
+                callback: Box::new(|| ()),
+                color: color::LaunchpadColor::GREEN
+            });
+            locked.render().unwrap();
+        }
+
+        assert_eq!(mock.get_light(0, 0).red, 127);
+        assert_eq!(mock.get_light(1, 0).blue, 127);
+        assert_eq!(mock.get_light(5, 3).green, 127);
+
+        mock.press_button(Button::Left);
+        assert_eq!(app.lock().unwrap().current_page, 2);
+        assert_eq!(mock.get_light(2, 0).red, 127);
+        assert_eq!(mock.get_light(5, 3).green, 0);
+    }
+}